@@ -1,16 +1,24 @@
 use std::{
     sync::{
-        mpsc::{self, Receiver, Sender},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
         Arc, Mutex,
     },
     thread,
+    time::Duration,
 };
 
-use crate::audio_backend::{
-    audio_device_manager::AudioDeviceManager, audio_pipeline::AudioPipeline,
-    audio_stream_manager::AudioStreamManager,
+use crate::{
+    audio_backend::{
+        audio_device_manager::AudioDeviceManager, audio_stream_manager::AudioStreamManager,
+        pipeline_controller::PipelineController, stream_telemetry::StreamTelemetry,
+    },
+    config::{audio_config::AudioConfig, config::Config},
 };
 
+// How often the chain is checked on between commands, so what the audio thread hands back is
+// freed even while nothing is being edited.
+const RESYNC_INTERVAL: Duration = Duration::from_secs(1);
+
 pub enum AudioCommand {
     Start,
     Stop,
@@ -19,19 +27,41 @@ pub enum AudioCommand {
 fn audio_thread(
     rx: Receiver<AudioCommand>,
     device_manager: Arc<Mutex<AudioDeviceManager>>,
-    audio_pipeline: Arc<Mutex<AudioPipeline>>,
+    pipeline_controller: Arc<Mutex<PipelineController>>,
+    audio_config: Arc<Mutex<AudioConfig>>,
+    telemetry: Arc<StreamTelemetry>,
 ) {
-    let mut stream_manager = AudioStreamManager::new();
+    let mut stream_manager = AudioStreamManager::new(telemetry);
+
+    loop {
+        let command = match rx.recv_timeout(RESYNC_INTERVAL) {
+            Ok(command) => command,
+            Err(RecvTimeoutError::Timeout) => {
+                pipeline_controller.lock().unwrap().resync();
+                continue;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
 
-    for command in rx {
         match command {
             AudioCommand::Start => match device_manager.lock() {
                 Ok(guard) => {
                     println!("Starting stream...");
+                    let config = audio_config.lock().unwrap().clone();
 
-                    stream_manager
-                        .run(&guard, audio_pipeline.clone())
-                        .expect("Failed to start streams");
+                    if let Err(err) = stream_manager.run(
+                        &guard,
+                        pipeline_controller.clone(),
+                        config.input_channel,
+                        &config.latency,
+                    ) {
+                        eprintln!("Failed to start streams: {:?}", err);
+                        let _ = stream_manager.stop();
+                        pipeline_controller
+                            .lock()
+                            .unwrap()
+                            .disconnect_audio_pipeline();
+                    }
                 }
                 Err(poisoned) => {
                     println!("{:#?}", poisoned)
@@ -40,6 +70,10 @@ fn audio_thread(
             AudioCommand::Stop => {
                 println!("Stopping stream...");
                 stream_manager.stop().expect("to stop streams");
+                pipeline_controller
+                    .lock()
+                    .unwrap()
+                    .disconnect_audio_pipeline();
             }
         }
     }
@@ -47,18 +81,46 @@ fn audio_thread(
 
 pub fn start_audio_thread(
     device_manager: Arc<Mutex<AudioDeviceManager>>,
-    audio_pipeline: Arc<Mutex<AudioPipeline>>,
+    pipeline_controller: Arc<Mutex<PipelineController>>,
+    audio_config: Arc<Mutex<AudioConfig>>,
+    telemetry: Arc<StreamTelemetry>,
 ) -> Sender<AudioCommand> {
     let (tx, rx) = mpsc::channel();
 
     thread::spawn(move || {
-        audio_thread(rx, device_manager, audio_pipeline);
+        audio_thread(
+            rx,
+            device_manager,
+            pipeline_controller,
+            audio_config,
+            telemetry,
+        );
     });
 
     tx
 }
 
-pub fn get_processor_impl_names() -> Vec<&'static str> {
-    // Has to be a better way.
-    vec!["amplifier", "screamer"]
+/// Stores the chain in the audio config once a second whenever it has changed, so the next
+/// session starts with the same tone.
+pub fn start_pipeline_autosave(
+    pipeline_controller: Arc<Mutex<PipelineController>>,
+    audio_config: Arc<Mutex<AudioConfig>>,
+) {
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+
+        let state = {
+            let mut controller = pipeline_controller.lock().unwrap();
+            if !controller.take_changed() {
+                continue;
+            }
+            controller.state()
+        };
+
+        let mut config = audio_config.lock().unwrap();
+        config.pipeline = Some(state);
+        if let Err(err) = config.save() {
+            eprintln!("Failed to save pipeline: {:?}", err);
+        }
+    });
 }
@@ -0,0 +1,103 @@
+/// The pipeline never carries more than a stereo pair.
+pub const MAX_CHANNELS: usize = 2;
+
+/// A planar block of audio passed through the processor chain. The guitar signal stays mono
+/// until a processor asks for stereo, so `channels()` is either 1 or 2.
+pub struct AudioBuffer {
+    data: Vec<Vec<f32>>,
+    channels: usize,
+    frames: usize,
+}
+
+impl AudioBuffer {
+    pub fn new(max_frames: usize) -> Self {
+        AudioBuffer {
+            data: vec![vec![0.0; max_frames]; MAX_CHANNELS],
+            channels: 1,
+            frames: 0,
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    pub fn channel(&self, channel: usize) -> &[f32] {
+        &self.data[channel][..self.frames]
+    }
+
+    pub fn channel_mut(&mut self, channel: usize) -> &mut [f32] {
+        &mut self.data[channel][..self.frames]
+    }
+
+    pub fn stereo_mut(&mut self) -> (&mut [f32], &mut [f32]) {
+        let (left, right) = self.data.split_at_mut(1);
+        (&mut left[0][..self.frames], &mut right[0][..self.frames])
+    }
+
+    /// Loads a mono block, resetting the buffer to a single channel.
+    pub fn load_mono(&mut self, input: &[f32]) {
+        self.frames = input.len();
+        self.channels = 1;
+        self.data[0][..self.frames].copy_from_slice(input);
+    }
+
+    /// Copies another block, channel count included, without reallocating.
+    pub fn copy_from(&mut self, other: &AudioBuffer) {
+        self.frames = other.frames;
+        self.channels = other.channels;
+        for channel in 0..self.channels {
+            self.data[channel][..self.frames].copy_from_slice(other.channel(channel));
+        }
+    }
+
+    /// Resets the buffer to `frames` frames of mono silence.
+    pub fn clear(&mut self, frames: usize) {
+        self.frames = frames;
+        self.channels = 1;
+        self.data[0][..frames].fill(0.0);
+    }
+
+    /// Adds another block of the same length, widening first if it is stereo.
+    pub fn add_from(&mut self, other: &AudioBuffer, gain: f32) {
+        if other.channels > self.channels {
+            self.widen_to_stereo();
+        }
+        for channel in 0..self.channels {
+            let source = other.channel(channel.min(other.channels - 1));
+            for (sample, added) in self.data[channel][..self.frames].iter_mut().zip(source) {
+                *sample += added * gain;
+            }
+        }
+    }
+
+    /// Copies the mono signal to the right channel so stereo processors can take over.
+    pub fn widen_to_stereo(&mut self) {
+        if self.channels == 1 {
+            let (left, right) = self.stereo_mut();
+            right.copy_from_slice(left);
+            self.channels = 2;
+        }
+    }
+
+    /// Writes the block into an interleaved device buffer, up- or down-mixing as needed.
+    pub fn write_interleaved(&self, output: &mut [f32], output_channels: usize) {
+        for (frame, out) in output.chunks_mut(output_channels).enumerate() {
+            let left = self.data[0][frame];
+            let right = self.data[self.channels - 1][frame];
+
+            match out.len() {
+                1 => out[0] = (left + right) * 0.5,
+                _ => {
+                    out[0] = left;
+                    out[1] = right;
+                    out[2..].fill(0.0);
+                }
+            }
+        }
+    }
+}
@@ -1,28 +1,543 @@
-use crate::audio_backend::processor_trait::Processor;
+use ringbuf::{HeapConsumer, HeapProducer};
+use serde::{Deserialize, Serialize};
 
+use crate::audio_backend::{
+    audio_buffer::AudioBuffer,
+    processor_trait::Processor,
+    routing::{RouteStep, MAX_SPLIT_DEPTH},
+};
+
+// Size of the realtime processor pool, reserved up front so edits never allocate there.
+pub const MAX_PROCESSORS: usize = 64;
+
+/// Chain edits sent from the UI thread to the audio callback. Processors are addressed by their
+/// slot in the realtime pool; the order they run in comes from the route.
+pub enum PipelineCommand {
+    AddProcessor(usize, ProcessorNode),
+    /// Swaps a routed processor for a new copy, such as after loading a file into it.
+    ReplaceProcessor(usize, ProcessorNode),
+    RemoveProcessor(usize),
+    /// Compiled on the UI thread; the old route is handed back as garbage.
+    SetRoute(Vec<RouteStep>),
+    /// Already validated on the UI side; the name is the descriptor's, so nothing is allocated.
+    SetParameter(usize, &'static str, f32),
+    SetSlotState(usize, SlotState),
+    /// Swaps in a whole new chain, crossfading from the old one. Later commands address the new
+    /// chain's pool.
+    ReplaceChain(Box<Chain>),
+}
+
+impl PipelineCommand {
+    /// Edits that change the signal path, and so would click if applied mid-signal.
+    fn is_structural(&self) -> bool {
+        matches!(
+            self,
+            PipelineCommand::ReplaceProcessor(..)
+                | PipelineCommand::RemoveProcessor(_)
+                | PipelineCommand::SetRoute(_)
+        )
+    }
+}
+
+/// Per-slot routing around a processor. A bypassed slot skips its processor entirely once the
+/// crossfade to the dry signal has finished.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SlotState {
+    pub bypassed: bool,
+    /// 0.0 is fully dry, 1.0 fully wet.
+    pub mix: f32,
+}
+
+impl SlotState {
+    pub fn new() -> Self {
+        SlotState {
+            bypassed: false,
+            mix: 1.0,
+        }
+    }
+
+    fn wet(&self) -> f32 {
+        if self.bypassed {
+            0.0
+        } else {
+            self.mix
+        }
+    }
+}
+
+impl Default for SlotState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A processor as it sits in the realtime chain, with its smoothed wet level.
+pub struct ProcessorNode {
+    processor: Box<dyn Processor>,
+    wet: Ramp,
+}
+
+impl ProcessorNode {
+    pub fn new(processor: Box<dyn Processor>, state: SlotState, fade_frames: usize) -> Self {
+        ProcessorNode {
+            processor,
+            wet: Ramp::new(state.wet(), fade_frames),
+        }
+    }
+
+    /// Runs the processor over `buffer`, mixing in the dry signal kept in `dry` when needed.
+    fn process(&mut self, buffer: &mut AudioBuffer, dry: &mut AudioBuffer) {
+        // True bypass: a fully dry slot doesn't run its processor at all.
+        if self.wet.is_settled_at(0.0) {
+            return;
+        }
+
+        if self.processor.output_channels() > buffer.channels() {
+            buffer.widen_to_stereo();
+        }
+
+        if self.wet.is_settled_at(1.0) {
+            self.processor.process_block(buffer);
+            return;
+        }
+
+        dry.copy_from(buffer);
+        self.processor.process_block(buffer);
+        for frame in 0..buffer.frames() {
+            let wet = self.wet.next();
+            for channel in 0..buffer.channels() {
+                let dry = dry.channel(channel)[frame];
+                let sample = &mut buffer.channel_mut(channel)[frame];
+                *sample = dry + wet * (*sample - dry);
+            }
+        }
+    }
+}
+
+/// A processor pool together with the route through it.
+pub struct Chain {
+    processors: Vec<Option<ProcessorNode>>,
+    route: Vec<RouteStep>,
+}
+
+impl Chain {
+    /// `processors` must hold `MAX_PROCESSORS` slots.
+    pub fn new(processors: Vec<Option<ProcessorNode>>, route: Vec<RouteStep>) -> Self {
+        Chain { processors, route }
+    }
+
+    fn process(&mut self, buffer: &mut AudioBuffer, scratch: &mut Scratch) {
+        let mut depth = 0;
+        for step in &self.route {
+            match *step {
+                RouteStep::Process(index) => {
+                    if let Some(node) = &mut self.processors[index] {
+                        node.process(buffer, &mut scratch.dry);
+                    }
+                }
+                RouteStep::SplitBegin => {
+                    scratch.split_inputs[depth].copy_from(buffer);
+                    scratch.split_sums[depth].clear(buffer.frames());
+                    depth += 1;
+                }
+                RouteStep::BranchBegin => buffer.copy_from(&scratch.split_inputs[depth - 1]),
+                RouteStep::BranchEnd(gain) => scratch.split_sums[depth - 1].add_from(buffer, gain),
+                RouteStep::SplitEnd => {
+                    depth -= 1;
+                    buffer.copy_from(&scratch.split_sums[depth]);
+                }
+            }
+        }
+    }
+}
+
+/// Working buffers shared by every chain the pipeline runs.
+struct Scratch {
+    // The input of the current processor, kept for the wet/dry mix.
+    dry: AudioBuffer,
+    // Per split depth: the signal fed to each branch, and the sum of the branches so far.
+    split_inputs: Vec<AudioBuffer>,
+    split_sums: Vec<AudioBuffer>,
+}
+
+/// Allocations handed back by the audio callback so they are freed off the realtime thread.
+pub enum PipelineGarbage {
+    Processor(ProcessorNode),
+    Route(Vec<RouteStep>),
+    Chain(Box<Chain>),
+}
+
+/// The realtime half of the processor chain. It is owned by the output stream callback and is
+/// only ever changed through `PipelineCommand`s, so processing never waits on a lock.
 pub struct AudioPipeline {
-    pub processors: Vec<Box<dyn Processor>>,
+    chain: Box<Chain>,
+    // The chain being faded out after a `ReplaceChain`, and its output.
+    previous: Option<Box<Chain>>,
+    previous_buffer: AudioBuffer,
+    crossfade: Ramp,
+    buffer: AudioBuffer,
+    scratch: Scratch,
+    max_block_size: usize,
+    commands: HeapConsumer<PipelineCommand>,
+    // Commands held back until the output has faded out. Sized to the queue, so it never grows.
+    pending: Vec<PipelineCommand>,
+    declick: Ramp,
+    garbage: HeapProducer<PipelineGarbage>,
 }
 
 impl AudioPipeline {
-    pub fn new() -> Self {
+    pub fn new(
+        chain: Chain,
+        max_block_size: usize,
+        fade_frames: usize,
+        crossfade_frames: usize,
+        commands: HeapConsumer<PipelineCommand>,
+        garbage: HeapProducer<PipelineGarbage>,
+    ) -> Self {
         AudioPipeline {
-            processors: Vec::new(),
+            chain: Box::new(chain),
+            previous: None,
+            previous_buffer: AudioBuffer::new(max_block_size),
+            crossfade: Ramp::new(1.0, crossfade_frames),
+            buffer: AudioBuffer::new(max_block_size),
+            scratch: Scratch {
+                dry: AudioBuffer::new(max_block_size),
+                split_inputs: (0..MAX_SPLIT_DEPTH)
+                    .map(|_| AudioBuffer::new(max_block_size))
+                    .collect(),
+                split_sums: (0..MAX_SPLIT_DEPTH)
+                    .map(|_| AudioBuffer::new(max_block_size))
+                    .collect(),
+            },
+            max_block_size,
+            pending: Vec::with_capacity(commands.capacity()),
+            commands,
+            declick: Ramp::new(1.0, fade_frames),
+            garbage,
+        }
+    }
+
+    /// Runs a block of mono guitar input through the chain and writes it to an interleaved
+    /// output buffer. The signal stays mono until a stereo processor widens it.
+    pub fn process_block(&mut self, input: &[f32], output: &mut [f32], output_channels: usize) {
+        self.receive_commands();
+
+        // Keep each block within the size the processors were prepared for.
+        let input_blocks = input.chunks(self.max_block_size);
+        let output_blocks = output.chunks_mut(self.max_block_size * output_channels);
+
+        for (input_block, output_block) in input_blocks.zip(output_blocks) {
+            self.buffer.load_mono(input_block);
+            self.chain.process(&mut self.buffer, &mut self.scratch);
+
+            if let Some(previous) = &mut self.previous {
+                self.previous_buffer.load_mono(input_block);
+                previous.process(&mut self.previous_buffer, &mut self.scratch);
+                self.mix_previous();
+            }
+
+            if !self.declick.is_settled_at(1.0) {
+                for frame in 0..self.buffer.frames() {
+                    let gain = self.declick.next();
+                    for channel in 0..self.buffer.channels() {
+                        self.buffer.channel_mut(channel)[frame] *= gain;
+                    }
+                }
+            }
+            if !self.pending.is_empty() && self.declick.is_settled_at(0.0) {
+                self.apply_pending();
+                self.declick.fade_to(1.0);
+            }
+
+            self.buffer.write_interleaved(output_block, output_channels);
+        }
+    }
+
+    /// Crossfades from the previous chain's output into the current one, and lets the previous
+    /// chain go once it can no longer be heard.
+    fn mix_previous(&mut self) {
+        if self.previous_buffer.channels() > self.buffer.channels() {
+            self.buffer.widen_to_stereo();
+        }
+
+        let previous_channels = self.previous_buffer.channels();
+        for frame in 0..self.buffer.frames() {
+            let gain = self.crossfade.next();
+            for channel in 0..self.buffer.channels() {
+                let previous = self
+                    .previous_buffer
+                    .channel(channel.min(previous_channels - 1))[frame];
+                let sample = &mut self.buffer.channel_mut(channel)[frame];
+                *sample = previous + gain * (*sample - previous);
+            }
+        }
+
+        if self.crossfade.is_settled_at(1.0) {
+            if let Some(previous) = self.previous.take() {
+                self.dispose(PipelineGarbage::Chain(previous));
+            }
+        }
+    }
+
+    /// Parameter changes apply straight away. A structural edit starts a fade out, and it and
+    /// everything after it waits in `pending` until the output is silent.
+    fn receive_commands(&mut self) {
+        while self.pending.len() < self.pending.capacity() {
+            let Some(command) = self.commands.pop() else {
+                break;
+            };
+
+            if self.pending.is_empty() && !command.is_structural() {
+                self.apply_command(command);
+            } else {
+                self.pending.push(command);
+                self.declick.fade_to(0.0);
+            }
+        }
+    }
+
+    fn apply_pending(&mut self) {
+        // Swapped out so commands can borrow `self`; the capacity is kept.
+        let mut pending = std::mem::take(&mut self.pending);
+        for command in pending.drain(..) {
+            self.apply_command(command);
+        }
+        self.pending = pending;
+    }
+
+    fn apply_command(&mut self, command: PipelineCommand) {
+        match command {
+            PipelineCommand::AddProcessor(index, node)
+            | PipelineCommand::ReplaceProcessor(index, node) => {
+                if let Some(replaced) = self.chain.processors[index].replace(node) {
+                    self.dispose(PipelineGarbage::Processor(replaced));
+                }
+            }
+            PipelineCommand::RemoveProcessor(index) => {
+                if let Some(node) = self.chain.processors[index].take() {
+                    self.dispose(PipelineGarbage::Processor(node));
+                }
+            }
+            PipelineCommand::SetRoute(route) => {
+                let old_route = std::mem::replace(&mut self.chain.route, route);
+                self.dispose(PipelineGarbage::Route(old_route));
+            }
+            PipelineCommand::SetParameter(index, name, value) => {
+                if let Some(node) = &mut self.chain.processors[index] {
+                    let _ = node.processor.set_parameter(name, value);
+                }
+            }
+            PipelineCommand::SetSlotState(index, state) => {
+                if let Some(node) = &mut self.chain.processors[index] {
+                    node.wet.fade_to(state.wet());
+                }
+            }
+            PipelineCommand::ReplaceChain(chain) => {
+                let old_chain = std::mem::replace(&mut self.chain, chain);
+                // A chain still fading out is cut short; the newest swap wins.
+                if let Some(faded) = self.previous.replace(old_chain) {
+                    self.dispose(PipelineGarbage::Chain(faded));
+                }
+                self.crossfade.jump_to(0.0);
+                self.crossfade.fade_to(1.0);
+            }
+        }
+    }
+
+    fn dispose(&mut self, garbage: PipelineGarbage) {
+        // Only dropped here if the UI thread has stopped collecting.
+        let _ = self.garbage.push(garbage);
+    }
+}
+
+/// A linear ramp towards a target gain, one step per frame. Used for the declick around chain
+/// edits, the per-slot wet/dry crossfade and the crossfade between whole chains.
+struct Ramp {
+    value: f32,
+    target: f32,
+    step: f32,
+}
+
+impl Ramp {
+    fn new(value: f32, fade_frames: usize) -> Self {
+        Ramp {
+            value,
+            target: value,
+            step: 1.0 / fade_frames.max(1) as f32,
+        }
+    }
+
+    fn jump_to(&mut self, value: f32) {
+        self.value = value;
+        self.target = value;
+    }
+
+    fn fade_to(&mut self, target: f32) {
+        self.target = target;
+    }
+
+    fn is_settled_at(&self, value: f32) -> bool {
+        self.value == value && self.target == value
+    }
+
+    fn next(&mut self) -> f32 {
+        self.value = if self.target > self.value {
+            (self.value + self.step).min(self.target)
+        } else {
+            (self.value - self.step).max(self.target)
+        };
+        self.value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use ringbuf::HeapRb;
+
+    use super::*;
+    use crate::audio_backend::processor_trait::{
+        ParameterDescriptor, ParameterError, ProcessorHashMapValue,
+    };
+
+    const FADE_FRAMES: usize = 4;
+
+    #[derive(Clone)]
+    struct Gain(f32);
+
+    impl Processor for Gain {
+        fn process(&mut self, _channel: usize, input: f32) -> f32 {
+            input * self.0
+        }
+
+        fn set_parameter(&mut self, _name: &str, _value: f32) -> Result<(), ParameterError> {
+            Ok(())
+        }
+
+        fn get_name(&self) -> &'static str {
+            "gain"
+        }
+
+        fn parameters(&self) -> &'static [ParameterDescriptor] {
+            &[]
+        }
+
+        fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+            HashMap::new()
+        }
+
+        fn clone_box(&self) -> Box<dyn Processor> {
+            Box::new(self.clone())
         }
     }
 
-    pub fn add_processor(&mut self, processor: Box<dyn Processor>) {
-        self.processors.push(processor);
+    // A chain of one `Gain` in pool slot 0.
+    fn gain_chain(gain: f32) -> Chain {
+        let mut processors: Vec<_> = (0..MAX_PROCESSORS).map(|_| None).collect();
+        processors[0] = Some(ProcessorNode::new(
+            Box::new(Gain(gain)),
+            SlotState::new(),
+            FADE_FRAMES,
+        ));
+        Chain::new(processors, vec![RouteStep::Process(0)])
     }
 
-    pub fn remove_processor(&mut self, processor_name: String) {
-        self.processors.retain(|x| x.get_name() != processor_name)
+    struct Harness {
+        pipeline: AudioPipeline,
+        commands: HeapProducer<PipelineCommand>,
+        garbage: HeapConsumer<PipelineGarbage>,
     }
 
-    pub fn process_sample(&self, mut sample: f32) -> f32 {
-        for processor in &self.processors {
-            sample = processor.process(sample)
+    impl Harness {
+        // Blocks are `FADE_FRAMES` long, so each fade fits in one.
+        fn new(chain: Chain) -> Self {
+            let (commands, commands_consumer) = HeapRb::new(16).split();
+            let (garbage_producer, garbage) = HeapRb::new(16).split();
+
+            Harness {
+                pipeline: AudioPipeline::new(
+                    chain,
+                    FADE_FRAMES,
+                    FADE_FRAMES,
+                    FADE_FRAMES,
+                    commands_consumer,
+                    garbage_producer,
+                ),
+                commands,
+                garbage,
+            }
+        }
+
+        fn send(&mut self, command: PipelineCommand) {
+            assert!(self.commands.push(command).is_ok());
+        }
+
+        // Runs a block of ones through the pipeline.
+        fn block(&mut self) -> Vec<f32> {
+            let input = [1.0; FADE_FRAMES];
+            let mut output = [0.0; FADE_FRAMES];
+            self.pipeline.process_block(&input, &mut output, 1);
+            output.to_vec()
         }
-        sample
+    }
+
+    #[test]
+    fn ramp_moves_one_step_per_frame() {
+        let mut ramp = Ramp::new(0.0, FADE_FRAMES);
+        ramp.fade_to(1.0);
+
+        let values: Vec<f32> = (0..FADE_FRAMES + 1).map(|_| ramp.next()).collect();
+        assert_eq!(values, [0.25, 0.5, 0.75, 1.0, 1.0]);
+        assert!(ramp.is_settled_at(1.0));
+
+        ramp.jump_to(0.0);
+        assert!(ramp.is_settled_at(0.0));
+    }
+
+    #[test]
+    fn structural_edits_wait_for_the_fade_out() {
+        let mut harness = Harness::new(gain_chain(2.0));
+        assert_eq!(harness.block(), [2.0; FADE_FRAMES]);
+
+        // Unrouting the processor fades out, applies the edit, then fades back in dry.
+        harness.send(PipelineCommand::SetRoute(Vec::new()));
+        assert_eq!(harness.block(), [1.5, 1.0, 0.5, 0.0]);
+        assert_eq!(harness.block(), [0.25, 0.5, 0.75, 1.0]);
+        assert_eq!(harness.block(), [1.0; FADE_FRAMES]);
+
+        assert!(matches!(
+            harness.garbage.pop(),
+            Some(PipelineGarbage::Route(_))
+        ));
+    }
+
+    #[test]
+    fn bypass_crossfades_to_dry() {
+        let mut harness = Harness::new(gain_chain(2.0));
+
+        harness.send(PipelineCommand::SetSlotState(
+            0,
+            SlotState {
+                bypassed: true,
+                mix: 1.0,
+            },
+        ));
+        assert_eq!(harness.block(), [1.75, 1.5, 1.25, 1.0]);
+        assert_eq!(harness.block(), [1.0; FADE_FRAMES]);
+    }
+
+    #[test]
+    fn replacing_the_chain_crossfades_and_frees_the_old_one() {
+        let mut harness = Harness::new(gain_chain(2.0));
+
+        harness.send(PipelineCommand::ReplaceChain(Box::new(gain_chain(3.0))));
+        assert_eq!(harness.block(), [2.25, 2.5, 2.75, 3.0]);
+        assert_eq!(harness.block(), [3.0; FADE_FRAMES]);
+
+        assert!(matches!(
+            harness.garbage.pop(),
+            Some(PipelineGarbage::Chain(_))
+        ));
     }
 }
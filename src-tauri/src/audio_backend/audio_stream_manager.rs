@@ -1,34 +1,55 @@
 use std::{
-    mem::MaybeUninit,
     sync::{Arc, Mutex},
+    time::Instant,
 };
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
 use cpal::{
     traits::{DeviceTrait, StreamTrait},
-    Device, Stream, StreamConfig,
+    BufferSize, Device, FromSample, Sample, SampleFormat, SampleRate, SizedSample, Stream,
+    StreamConfig, SupportedBufferSize, SupportedStreamConfig,
 };
-use ringbuf::{Consumer, HeapRb, Producer, SharedRb};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
 
-use super::{audio_device_manager::AudioDeviceManager, audio_pipeline::AudioPipeline};
+use crate::config::audio_config::LatencySettings;
+
+use super::{
+    audio_device_manager::AudioDeviceManager, pipeline_controller::PipelineController,
+    resampler::Resampler, stream_telemetry::StreamTelemetry,
+};
+
+// Used when the host picks the callback size itself.
+const DEFAULT_MAX_BLOCK_SIZE: usize = 1024;
+
+// Upper bound for the pre-fill, manual or adaptive.
+const MAX_LATENCY_MS: f32 = 500.0;
+
+// How much faster than real time the input is read while the backlog drains down to a shrunk
+// pre-fill. 0.2% is a pitch shift of about 3.5 cents, too small to hear, and clears each 1 ms
+// step in half a second.
+const DRAIN_DRIFT: f64 = 0.002;
 
 pub struct AudioStreamManager {
     input_stream: Option<Stream>,
     output_stream: Option<Stream>,
+    telemetry: Arc<StreamTelemetry>,
 }
 
 impl AudioStreamManager {
-    pub fn new() -> Self {
+    pub fn new(telemetry: Arc<StreamTelemetry>) -> Self {
         AudioStreamManager {
             input_stream: None,
             output_stream: None,
+            telemetry,
         }
     }
 
     pub fn run(
         &mut self,
         audio_device_manager: &AudioDeviceManager,
-        audio_pipeline: Arc<Mutex<AudioPipeline>>,
+        pipeline_controller: Arc<Mutex<PipelineController>>,
+        input_channel: usize,
+        latency: &LatencySettings,
     ) -> Result<(), Error> {
         let input_device = &audio_device_manager.input_device;
         let output_device = &audio_device_manager.output_device;
@@ -36,31 +57,116 @@ impl AudioStreamManager {
         println!("Input Device: {:#?}", input_device.name().unwrap());
         println!("Output Device: {:#?}", output_device.name().unwrap());
 
-        let config: StreamConfig = input_device.default_input_config().unwrap().config();
+        let supported_input_config = input_device.default_input_config()?;
+        let input_config = Self::stream_config(&supported_input_config, latency.buffer_size);
+        let supported_output_config =
+            Self::negotiate_output_config(output_device, input_config.sample_rate)?;
+        let output_config = Self::stream_config(&supported_output_config, latency.buffer_size);
+
+        println!(
+            "Input format: {} Hz, {} channels, {:?}",
+            input_config.sample_rate.0, input_config.channels, input_config.buffer_size
+        );
+        println!(
+            "Output format: {} Hz, {} channels, {:?}",
+            output_config.sample_rate.0, output_config.channels, output_config.buffer_size
+        );
 
         // Create a delay in case the input and output devices aren't synced.
-        let latency_frames = (300.0 / 1_000.0) * config.sample_rate.0 as f32;
-        let latency_samples = latency_frames as usize * config.channels as usize;
+        // Only the guitar channel is shared, at the input rate, so one sample is one frame.
+        let prefill = Prefill::new(latency, input_config.sample_rate.0);
 
-        // The buffer to share samples
-        let ring = HeapRb::<f32>::new(latency_samples * 2);
+        // The buffer to share samples, with room for the largest pre-fill.
+        let ring = HeapRb::<f32>::new(prefill.max * 2);
         let (mut producer, consumer) = ring.split();
 
         // Fill the samples with 0.0 equal to the length of the delay.
-        for _ in 0..latency_samples {
-            // The ring buffer has twice as much space as necessary to add latency here,
+        for _ in 0..prefill.target {
+            // The ring buffer has twice as much space as the largest pre-fill,
             // so this should never fail
             producer.push(0.0).unwrap();
         }
 
-        let input_stream =
-            Self::get_input_stream(&input_device, producer).expect("Failed to get input stream");
+        self.telemetry.reset(
+            input_config.sample_rate.0,
+            output_config.sample_rate.0,
+            prefill.target,
+        );
+
+        let input_stream = match supported_input_config.sample_format() {
+            SampleFormat::F32 => Self::get_input_stream::<f32>(
+                input_device,
+                &input_config,
+                producer,
+                input_channel,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::I16 => Self::get_input_stream::<i16>(
+                input_device,
+                &input_config,
+                producer,
+                input_channel,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::I32 => Self::get_input_stream::<i32>(
+                input_device,
+                &input_config,
+                producer,
+                input_channel,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::U16 => Self::get_input_stream::<u16>(
+                input_device,
+                &input_config,
+                producer,
+                input_channel,
+                self.telemetry.clone(),
+            ),
+            format => Err(anyhow!("Unsupported input sample format {}", format)),
+        }?;
 
-        let output_stream = Self::get_output_stream(&output_device, consumer, audio_pipeline)
-            .expect("Failed to get output stream");
+        let output_stream = match supported_output_config.sample_format() {
+            SampleFormat::F32 => Self::get_output_stream::<f32>(
+                output_device,
+                &output_config,
+                input_config.sample_rate,
+                consumer,
+                prefill,
+                pipeline_controller,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::I16 => Self::get_output_stream::<i16>(
+                output_device,
+                &output_config,
+                input_config.sample_rate,
+                consumer,
+                prefill,
+                pipeline_controller,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::I32 => Self::get_output_stream::<i32>(
+                output_device,
+                &output_config,
+                input_config.sample_rate,
+                consumer,
+                prefill,
+                pipeline_controller,
+                self.telemetry.clone(),
+            ),
+            SampleFormat::U16 => Self::get_output_stream::<u16>(
+                output_device,
+                &output_config,
+                input_config.sample_rate,
+                consumer,
+                prefill,
+                pipeline_controller,
+                self.telemetry.clone(),
+            ),
+            format => Err(anyhow!("Unsupported output sample format {}", format)),
+        }?;
 
-        input_stream.play().expect("to play input stream");
-        output_stream.play().expect("to play input stream");
+        input_stream.play()?;
+        output_stream.play()?;
 
         self.input_stream = Some(input_stream);
         self.output_stream = Some(output_stream);
@@ -76,53 +182,313 @@ impl AudioStreamManager {
         Ok(())
     }
 
-    fn get_input_stream(
+    /// Prefers an output format at the input rate so no resampling is needed, in f32 if the
+    /// device has it, falling back to the device default.
+    fn negotiate_output_config(
+        output_device: &Device,
+        input_rate: SampleRate,
+    ) -> Result<SupportedStreamConfig, Error> {
+        let default_config = output_device.default_output_config()?;
+
+        let matching_config = output_device
+            .supported_output_configs()?
+            .filter(|range| {
+                range.channels() == default_config.channels()
+                    && range.min_sample_rate() <= input_rate
+                    && input_rate <= range.max_sample_rate()
+            })
+            .min_by_key(|range| range.sample_format() != SampleFormat::F32);
+
+        Ok(match matching_config {
+            Some(range) => range.with_sample_rate(input_rate),
+            None => default_config,
+        })
+    }
+
+    /// Applies the requested device buffer size, clamped to what the device supports.
+    fn stream_config(supported: &SupportedStreamConfig, buffer_size: Option<u32>) -> StreamConfig {
+        let mut config = supported.config();
+
+        if let Some(frames) = buffer_size {
+            config.buffer_size = match supported.buffer_size() {
+                SupportedBufferSize::Range { min, max } => {
+                    BufferSize::Fixed(frames.clamp(*min, *max))
+                }
+                SupportedBufferSize::Unknown => BufferSize::Fixed(frames),
+            };
+        }
+
+        config
+    }
+
+    /// Streams the guitar channel into `producer`, converted to f32 from the device format `T`.
+    fn get_input_stream<T>(
         input_device: &Device,
-        mut producer: Producer<f32, Arc<SharedRb<f32, Vec<MaybeUninit<f32>>>>>,
-    ) -> Result<Stream, anyhow::Error> {
-        let config: StreamConfig = input_device.default_input_config().unwrap().into();
-        let input_data_fn = move |data: &[f32], _: &cpal::InputCallbackInfo| {
+        config: &StreamConfig,
+        mut producer: HeapProducer<f32>,
+        input_channel: usize,
+        telemetry: Arc<StreamTelemetry>,
+    ) -> Result<Stream, anyhow::Error>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = config.channels as usize;
+        let input_channel = input_channel.min(channels - 1);
+
+        let input_data_fn = move |data: &[T], _: &cpal::InputCallbackInfo| {
             let mut output_fell_behind = false;
-            for &sample in data {
-                if producer.push(sample).is_err() {
+            for frame in data.chunks(channels) {
+                if producer
+                    .push(f32::from_sample(frame[input_channel]))
+                    .is_err()
+                {
                     output_fell_behind = true;
                 }
             }
+            telemetry.record_input_block(data.len() / channels);
+
             if output_fell_behind {
-                // eprintln!("output stream fell behind: try increasing latency");
+                telemetry.record_overrun();
             }
         };
-        Ok(input_device.build_input_stream(&config, input_data_fn, Self::err_fn, None)?)
+        Ok(input_device.build_input_stream(config, input_data_fn, Self::err_fn, None)?)
     }
 
-    fn get_output_stream(
+    /// Runs the chain in f32 and converts what it writes to the device format `T`.
+    fn get_output_stream<T>(
         output_device: &Device,
-        mut consumer: Consumer<f32, Arc<SharedRb<f32, Vec<MaybeUninit<f32>>>>>,
-        audio_pipeline: Arc<Mutex<AudioPipeline>>,
-    ) -> Result<Stream, anyhow::Error> {
-        let config = output_device.default_output_config().unwrap().config();
+        config: &StreamConfig,
+        input_rate: SampleRate,
+        mut consumer: HeapConsumer<f32>,
+        mut prefill: Prefill,
+        pipeline_controller: Arc<Mutex<PipelineController>>,
+        telemetry: Arc<StreamTelemetry>,
+    ) -> Result<Stream, anyhow::Error>
+    where
+        T: SizedSample + FromSample<f32>,
+    {
+        let output_channels = config.channels as usize;
+        let max_block_size = Self::max_block_size(config);
+
+        // The callback owns its own copy of the chain; edits reach it through the controller.
+        let mut audio_pipeline = pipeline_controller
+            .lock()
+            .unwrap()
+            .create_audio_pipeline(config.sample_rate.0 as f32, max_block_size);
+        let mut guitar = vec![0.0; max_block_size];
+        let mut output = vec![0.0; max_block_size * output_channels];
+        let mut resampler = Resampler::new(input_rate.0, config.sample_rate.0);
+        let input_step = input_rate.0 as f64 / config.sample_rate.0 as f64;
+        let shrink_interval = config.sample_rate.0 as usize / 2;
+
+        let output_data_fn = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            let started = Instant::now();
+            let frames = data.len() / output_channels;
+
+            // After an underrun, stay silent until the pre-fill has been rebuilt.
+            if prefill.priming && consumer.len() < prefill.target {
+                data.fill(T::EQUILIBRIUM);
+                telemetry.record_output_block(frames, consumer.len(), prefill.target);
+                telemetry.record_callback(started.elapsed(), frames);
+                return;
+            }
+            prefill.priming = false;
 
-        let output_data_fn = move |data: &mut [f32], _: &cpal::OutputCallbackInfo| {
             let mut input_fell_behind = false;
-            for sample in data {
-                *sample = match consumer.pop() {
-                    Some(s) => audio_pipeline.lock().unwrap().process_sample(s),
-
-                    None => {
-                        input_fell_behind = true;
-                        0.0
-                    }
-                };
+            resampler.set_drift(prefill.drift());
+            for block in data.chunks_mut(max_block_size * output_channels) {
+                let input = &mut guitar[..block.len() / output_channels];
+                // Also used at matching rates, where it copies, so it can drift while draining.
+                if !resampler.process(input, || consumer.pop()) {
+                    input_fell_behind = true;
+                }
+
+                let output = &mut output[..block.len()];
+                audio_pipeline.process_block(input, output, output_channels);
+                for (sample, value) in block.iter_mut().zip(output.iter()) {
+                    *sample = T::from_sample(*value);
+                }
             }
+
             if input_fell_behind {
-                // eprintln!("input stream fell behind: try increasing latency");
+                telemetry.record_underrun();
+                prefill.underrun();
+            } else {
+                prefill.played(frames, frames as f64 * input_step, shrink_interval);
             }
+            telemetry.record_output_block(frames, consumer.len(), prefill.target);
+            telemetry.record_callback(started.elapsed(), frames);
         };
 
-        Ok(output_device.build_output_stream(&config, output_data_fn, Self::err_fn, None)?)
+        Ok(output_device.build_output_stream(config, output_data_fn, Self::err_fn, None)?)
+    }
+
+    fn max_block_size(config: &StreamConfig) -> usize {
+        match config.buffer_size {
+            BufferSize::Fixed(frames) => frames as usize,
+            BufferSize::Default => DEFAULT_MAX_BLOCK_SIZE,
+        }
     }
 
     fn err_fn(err: cpal::StreamError) {
         eprintln!("an error occurred on stream: {}", err);
     }
 }
+
+/// How many input frames are kept queued ahead of the output stream. In adaptive mode the
+/// target shrinks while playback is clean, and backs off for good once an underrun occurs.
+/// Frames above a shrunk target are drained by reading the input slightly fast.
+struct Prefill {
+    target: usize,
+    step: usize,
+    min: usize,
+    max: usize,
+    adaptive: bool,
+    settled: bool,
+    priming: bool,
+    clean_frames: usize,
+    // Input frames still queued above the target.
+    draining: f64,
+}
+
+impl Prefill {
+    fn new(latency: &LatencySettings, input_rate: u32) -> Self {
+        let frames_per_ms = input_rate as f32 / 1_000.0;
+        let max = (MAX_LATENCY_MS * frames_per_ms) as usize;
+        let step = (frames_per_ms as usize).max(1);
+
+        Prefill {
+            target: ((latency.latency_ms.max(0.0) * frames_per_ms) as usize).min(max),
+            step,
+            min: step,
+            max,
+            adaptive: latency.adaptive,
+            settled: false,
+            priming: false,
+            clean_frames: 0,
+            draining: 0.0,
+        }
+    }
+
+    fn underrun(&mut self) {
+        self.priming = true;
+        self.clean_frames = 0;
+        self.draining = 0.0;
+
+        if self.adaptive {
+            self.target = (self.target + self.step * 2).min(self.max);
+            self.settled = true;
+        }
+    }
+
+    /// The drift to read input with, while there is a backlog to drain.
+    fn drift(&self) -> f64 {
+        if self.draining > 0.0 {
+            DRAIN_DRIFT
+        } else {
+            0.0
+        }
+    }
+
+    /// Records `frames` of clean output, which read `input_frames` of input at the nominal rate.
+    /// Shrinks the target by `step` every `shrink_interval` frames once the last step has drained.
+    fn played(&mut self, frames: usize, input_frames: f64, shrink_interval: usize) {
+        if self.draining > 0.0 {
+            self.draining = (self.draining - input_frames * DRAIN_DRIFT).max(0.0);
+            return;
+        }
+
+        if !self.adaptive || self.settled || self.target <= self.min {
+            return;
+        }
+
+        self.clean_frames += frames;
+        if self.clean_frames < shrink_interval {
+            return;
+        }
+
+        self.clean_frames = 0;
+        self.target -= self.step;
+        self.draining = self.step as f64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+    const BLOCK: usize = 480;
+
+    fn adaptive(latency_ms: f32) -> Prefill {
+        Prefill::new(
+            &LatencySettings {
+                latency_ms,
+                buffer_size: None,
+                adaptive: true,
+            },
+            RATE,
+        )
+    }
+
+    // Plays clean blocks at matching rates until `frames` have gone by.
+    fn play(prefill: &mut Prefill, frames: usize) {
+        for _ in 0..frames / BLOCK {
+            prefill.played(BLOCK, BLOCK as f64, RATE as usize / 2);
+        }
+    }
+
+    #[test]
+    fn shrinks_a_step_at_a_time_and_drains_without_skipping() {
+        let mut prefill = adaptive(10.0);
+        assert_eq!(prefill.target, 480);
+        assert_eq!(prefill.drift(), 0.0);
+
+        play(&mut prefill, RATE as usize / 2);
+        assert_eq!(prefill.target, 432);
+        assert_eq!(prefill.drift(), DRAIN_DRIFT);
+
+        // 48 frames at 0.2% fast takes 24,000 frames, during which the target holds.
+        play(&mut prefill, RATE as usize / 2 - BLOCK);
+        assert_eq!(prefill.target, 432);
+        assert!(prefill.drift() > 0.0);
+        play(&mut prefill, BLOCK);
+        assert_eq!(prefill.drift(), 0.0);
+
+        play(&mut prefill, RATE as usize / 2);
+        assert_eq!(prefill.target, 384);
+    }
+
+    #[test]
+    fn stops_at_the_minimum() {
+        let mut prefill = adaptive(2.0);
+        play(&mut prefill, RATE as usize * 10);
+
+        assert_eq!(prefill.target, prefill.min);
+        assert_eq!(prefill.drift(), 0.0);
+    }
+
+    #[test]
+    fn backs_off_for_good_after_an_underrun() {
+        let mut prefill = adaptive(10.0);
+        play(&mut prefill, RATE as usize / 2);
+        prefill.underrun();
+
+        assert!(prefill.priming);
+        assert_eq!(prefill.target, 432 + 96);
+        assert_eq!(prefill.drift(), 0.0);
+
+        play(&mut prefill, RATE as usize * 10);
+        assert_eq!(prefill.target, 432 + 96);
+    }
+
+    #[test]
+    fn manual_latency_stays_put() {
+        let mut prefill = Prefill::new(&LatencySettings::default(), RATE);
+        play(&mut prefill, RATE as usize * 10);
+
+        assert_eq!(prefill.target, 30 * 48);
+        assert_eq!(prefill.drift(), 0.0);
+    }
+}
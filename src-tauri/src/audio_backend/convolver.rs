@@ -0,0 +1,283 @@
+use std::{
+    f32::consts::TAU,
+    ops::{Add, Mul, Sub},
+    sync::Arc,
+};
+
+use super::audio_buffer::MAX_CHANNELS;
+
+// Samples per partition. The convolver adds this much latency.
+pub const PARTITION_SIZE: usize = 128;
+const FFT_SIZE: usize = PARTITION_SIZE * 2;
+
+#[derive(Clone, Copy)]
+struct Complex {
+    re: f32,
+    im: f32,
+}
+
+impl Complex {
+    const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re + other.re,
+            im: self.im + other.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re - other.re,
+            im: self.im - other.im,
+        }
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex {
+            re: self.re * other.re - self.im * other.im,
+            im: self.re * other.im + self.im * other.re,
+        }
+    }
+}
+
+/// An in-place radix-2 FFT of `FFT_SIZE` points, with its tables worked out up front.
+struct Fft {
+    twiddles: Vec<Complex>,
+    bit_reversed: Vec<usize>,
+}
+
+impl Fft {
+    fn new() -> Self {
+        let bits = FFT_SIZE.trailing_zeros();
+
+        Fft {
+            twiddles: (0..FFT_SIZE / 2)
+                .map(|k| {
+                    let angle = -TAU * k as f32 / FFT_SIZE as f32;
+                    Complex {
+                        re: angle.cos(),
+                        im: angle.sin(),
+                    }
+                })
+                .collect(),
+            bit_reversed: (0..FFT_SIZE)
+                .map(|index| index.reverse_bits() >> (usize::BITS - bits))
+                .collect(),
+        }
+    }
+
+    /// The inverse is left unscaled.
+    fn process(&self, data: &mut [Complex], inverse: bool) {
+        for (index, reversed) in self.bit_reversed.iter().enumerate() {
+            if *reversed > index {
+                data.swap(index, *reversed);
+            }
+        }
+
+        let mut length = 2;
+        while length <= FFT_SIZE {
+            let half = length / 2;
+            let stride = FFT_SIZE / length;
+
+            for start in (0..FFT_SIZE).step_by(length) {
+                for k in 0..half {
+                    let mut twiddle = self.twiddles[k * stride];
+                    if inverse {
+                        twiddle.im = -twiddle.im;
+                    }
+
+                    let even = data[start + k];
+                    let odd = data[start + k + half] * twiddle;
+                    data[start + k] = even + odd;
+                    data[start + k + half] = even - odd;
+                }
+            }
+
+            length *= 2;
+        }
+    }
+}
+
+/// Convolves a signal with an impulse response using uniformly partitioned overlap-save
+/// convolution, so long responses cost one FFT per partition of input rather than one
+/// multiply per tap per sample. Everything is allocated in `new`.
+#[derive(Clone)]
+pub struct Convolver {
+    fft: Arc<Fft>,
+    // The impulse response cut into partitions, each transformed.
+    partitions: Arc<Vec<Vec<Complex>>>,
+    channels: [ConvolverChannel; MAX_CHANNELS],
+    // Where the partitions' products are summed.
+    spectrum: Vec<Complex>,
+}
+
+#[derive(Clone)]
+struct ConvolverChannel {
+    // The previous partition of input followed by the one being filled.
+    input: Vec<f32>,
+    output: Vec<f32>,
+    position: usize,
+    // Spectra of the most recent input partitions, one per impulse response partition.
+    history: Vec<Vec<Complex>>,
+    newest: usize,
+}
+
+impl Convolver {
+    pub fn new(impulse_response: &[f32]) -> Self {
+        let fft = Fft::new();
+
+        let partitions: Vec<Vec<Complex>> = impulse_response
+            .chunks(PARTITION_SIZE)
+            .map(|chunk| {
+                let mut partition = vec![Complex::ZERO; FFT_SIZE];
+                for (bin, sample) in partition.iter_mut().zip(chunk) {
+                    bin.re = *sample;
+                }
+                fft.process(&mut partition, false);
+                partition
+            })
+            .collect();
+        let count = partitions.len().max(1);
+
+        Convolver {
+            fft: Arc::new(fft),
+            partitions: Arc::new(partitions),
+            channels: std::array::from_fn(|_| ConvolverChannel {
+                input: vec![0.0; FFT_SIZE],
+                output: vec![0.0; PARTITION_SIZE],
+                position: 0,
+                history: vec![vec![Complex::ZERO; FFT_SIZE]; count],
+                newest: 0,
+            }),
+            spectrum: vec![Complex::ZERO; FFT_SIZE],
+        }
+    }
+
+    pub fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let state = &mut self.channels[channel];
+
+        state.input[PARTITION_SIZE + state.position] = input;
+        let output = state.output[state.position];
+
+        state.position += 1;
+        if state.position == PARTITION_SIZE {
+            state.position = 0;
+            self.convolve(channel);
+        }
+
+        output
+    }
+
+    /// Runs once a partition of input has been collected, producing the next partition of output.
+    fn convolve(&mut self, channel: usize) {
+        let state = &mut self.channels[channel];
+        let count = state.history.len();
+
+        state.newest = (state.newest + 1) % count;
+        let block = &mut state.history[state.newest];
+        for (bin, sample) in block.iter_mut().zip(&state.input) {
+            *bin = Complex {
+                re: *sample,
+                im: 0.0,
+            };
+        }
+        self.fft.process(block, false);
+        state.input.copy_within(PARTITION_SIZE.., 0);
+
+        self.spectrum.fill(Complex::ZERO);
+        for (age, partition) in self.partitions.iter().enumerate() {
+            let block = &state.history[(state.newest + count - age) % count];
+            for ((sum, input), response) in self.spectrum.iter_mut().zip(block).zip(partition) {
+                *sum = *sum + *input * *response;
+            }
+        }
+        self.fft.process(&mut self.spectrum, true);
+
+        // The first half wrapped around; only the second is a valid linear convolution.
+        let scale = 1.0 / FFT_SIZE as f32;
+        for (output, bin) in state
+            .output
+            .iter_mut()
+            .zip(&self.spectrum[PARTITION_SIZE..])
+        {
+            *output = bin.re * scale;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A fixed pseudo-random signal between -1 and 1.
+    fn noise(length: usize, mut seed: u32) -> Vec<f32> {
+        (0..length)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    fn direct_convolution(input: &[f32], impulse_response: &[f32]) -> Vec<f32> {
+        (0..input.len())
+            .map(|n| {
+                impulse_response
+                    .iter()
+                    .take(n + 1)
+                    .enumerate()
+                    .map(|(k, tap)| tap * input[n - k])
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn matches_direct_convolution() {
+        // Several partitions, the last one partly filled.
+        let impulse_response = noise(PARTITION_SIZE * 5 + 37, 1);
+        let input = noise(PARTITION_SIZE * 12, 2);
+        let expected = direct_convolution(&input, &impulse_response);
+
+        let mut convolver = Convolver::new(&impulse_response);
+        let output: Vec<f32> = input
+            .iter()
+            .map(|sample| convolver.process(0, *sample))
+            .collect();
+
+        // The output is one partition late.
+        assert!(output[..PARTITION_SIZE].iter().all(|sample| *sample == 0.0));
+        for (n, (output, expected)) in output[PARTITION_SIZE..].iter().zip(&expected).enumerate() {
+            assert!(
+                (output - expected).abs() < 1e-4,
+                "sample {}: {} != {}",
+                n,
+                output,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_channels_apart() {
+        let mut convolver = Convolver::new(&[1.0, 0.5]);
+
+        for n in 0..PARTITION_SIZE * 2 {
+            convolver.process(0, if n == 0 { 1.0 } else { 0.0 });
+            assert_eq!(convolver.process(1, 0.0), 0.0);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+/// A circular buffer read back at fractional delays, for echoes and modulation.
+#[derive(Clone)]
+pub struct DelayLine {
+    buffer: Vec<f32>,
+    write_position: usize,
+}
+
+impl DelayLine {
+    /// Holds up to `max_delay` samples.
+    pub fn new(max_delay: usize) -> Self {
+        DelayLine {
+            buffer: vec![0.0; max_delay + 2],
+            write_position: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: f32) {
+        self.buffer[self.write_position] = sample;
+        self.write_position = (self.write_position + 1) % self.buffer.len();
+    }
+
+    /// Reads `delay` samples back, where 1 is the sample pushed last, interpolating between
+    /// samples. The delay is clamped to what the line holds.
+    pub fn read(&self, delay: f32) -> f32 {
+        let length = self.buffer.len();
+        // Not `clamp`, which panics on the empty line a processor holds before `prepare`.
+        let delay = delay.min((length - 2) as f32).max(1.0);
+        let position = (self.write_position + length) as f32 - delay;
+        let index = position as usize;
+        let fraction = position - index as f32;
+
+        let current = self.buffer[index % length];
+        let next = self.buffer[(index + 1) % length];
+        current + fraction * (next - current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_fractional_delay_interpolates_between_neighbours() {
+        let mut line = DelayLine::new(8);
+        line.push(1.0);
+        line.push(3.0);
+
+        assert_eq!(line.read(1.0), 3.0);
+        assert_eq!(line.read(2.0), 1.0);
+        assert_eq!(line.read(1.5), 2.0);
+        assert_eq!(line.read(1.25), 2.5);
+    }
+
+    #[test]
+    fn clamps_the_delay_to_the_line() {
+        let mut line = DelayLine::new(4);
+        for sample in 1..=6 {
+            line.push(sample as f32);
+        }
+
+        assert_eq!(line.read(0.0), 6.0);
+        assert_eq!(line.read(100.0), 3.0);
+    }
+}
@@ -0,0 +1,29 @@
+/// The linear gain for a level in decibels.
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+/// One-pole smoothing coefficient that covers most of the way to a new target in `ms`.
+pub fn time_coefficient(ms: f32, sample_rate: f32) -> f32 {
+    (-1.0 / (ms * 0.001 * sample_rate)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_decibels_to_gain() {
+        assert_eq!(db_to_gain(0.0), 1.0);
+        assert!((db_to_gain(-20.0) - 0.1).abs() < 1e-6);
+        assert!((db_to_gain(6.0) - 1.995).abs() < 1e-3);
+    }
+
+    // After `ms`, a one-pole filter has 1/e of the way left to go.
+    #[test]
+    fn time_coefficient_covers_the_time_constant() {
+        let coefficient = time_coefficient(10.0, 48_000.0);
+        let remaining = coefficient.powi(480);
+        assert!((remaining - (-1f32).exp()).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,223 @@
+use std::{collections::HashMap, f32::consts::TAU};
+
+use super::{
+    audio_buffer::MAX_CHANNELS,
+    processor_trait::{ParameterDescriptor, ParameterKind, ParameterScale},
+    tap_tempo::{note_length_ms, TEMPO_PARAMETER},
+};
+
+/// Every modulation effect lists this with `DIVISION_PARAMETER` and `TEMPO_PARAMETER`, plus a
+/// "rate" descriptor with a range to suit it.
+pub const WAVEFORM_PARAMETER: ParameterDescriptor = ParameterDescriptor {
+    name: "waveform",
+    label: "Waveform",
+    min: 0.0,
+    max: (WAVEFORMS.len() - 1) as f32,
+    default: 0.0,
+    unit: "",
+    scale: ParameterScale::Linear,
+    kind: ParameterKind::Choice {
+        options: &["Sine", "Triangle", "Square", "Saw Up", "Saw Down", "Random"],
+    },
+};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    SawUp,
+    SawDown,
+    SampleAndHold,
+}
+
+/// In the order of the `waveform` parameter's values.
+const WAVEFORMS: [Waveform; 6] = [
+    Waveform::Sine,
+    Waveform::Triangle,
+    Waveform::Square,
+    Waveform::SawUp,
+    Waveform::SawDown,
+    Waveform::SampleAndHold,
+];
+
+/// A low-frequency oscillator running between -1 and 1. The rate is either set in Hz or
+/// synced to a note length at `tempo`. Each channel keeps its own phase, so an effect can start
+/// the right channel part-way through the cycle for a wider sound.
+#[derive(Clone)]
+pub struct Lfo {
+    pub waveform: Waveform,
+    pub rate: f32,
+    pub division: usize,
+    pub tempo: f32,
+    sample_rate: f32,
+    increment: f32,
+    phases: [f32; MAX_CHANNELS],
+    held: [f32; MAX_CHANNELS],
+    random_state: u32,
+}
+
+impl Lfo {
+    pub fn new(rate: f32) -> Self {
+        let mut lfo = Lfo {
+            waveform: Waveform::Sine,
+            rate,
+            division: 0,
+            tempo: TEMPO_PARAMETER.default,
+            sample_rate: 44_100.0,
+            increment: 0.0,
+            phases: [0.0; MAX_CHANNELS],
+            held: [0.0; MAX_CHANNELS],
+            random_state: 0x9E37_79B9,
+        };
+        lfo.update_increment();
+        lfo
+    }
+
+    /// Restarts every channel, offsetting each by `spread` of a cycle from the one before.
+    pub fn prepare(&mut self, sample_rate: f32, spread: f32) {
+        self.sample_rate = sample_rate;
+        self.phases = std::array::from_fn(|channel| (channel as f32 * spread).fract());
+        self.update_increment();
+    }
+
+    /// Applies one of the LFO's parameters, returning false if `name` isn't one of them.
+    /// The value is expected to have been validated by the processor.
+    pub fn set_parameter(&mut self, name: &str, value: f32) -> bool {
+        match name {
+            "rate" => self.rate = value,
+            "waveform" => self.waveform = WAVEFORMS[value.round() as usize],
+            "division" => self.division = value.round() as usize,
+            "tempo" => self.tempo = value,
+            _ => return false,
+        }
+        self.update_increment();
+        true
+    }
+
+    /// Adds the LFO's parameters to a processor's `to_hash_map` details.
+    pub fn insert_details(&self, details: &mut HashMap<String, f32>) {
+        let waveform = WAVEFORMS
+            .iter()
+            .position(|waveform| *waveform == self.waveform)
+            .unwrap_or(0);
+
+        details.insert("rate".to_string(), self.rate);
+        details.insert("waveform".to_string(), waveform as f32);
+        details.insert("division".to_string(), self.division as f32);
+        details.insert("tempo".to_string(), self.tempo);
+    }
+
+    /// Returns the channel's current value and moves it on by one sample.
+    pub fn next(&mut self, channel: usize) -> f32 {
+        let phase = self.phases[channel];
+
+        let value = match self.waveform {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::SawUp => 2.0 * phase - 1.0,
+            Waveform::SawDown => 1.0 - 2.0 * phase,
+            Waveform::SampleAndHold => self.held[channel],
+        };
+
+        let next_phase = phase + self.increment;
+        if next_phase >= 1.0 {
+            self.held[channel] = self.random();
+        }
+        self.phases[channel] = next_phase.fract();
+
+        value
+    }
+
+    /// The rate in Hz, from the tempo when synced.
+    fn frequency(&self) -> f32 {
+        note_length_ms(self.division, self.tempo).map_or(self.rate, |ms| 1000.0 / ms)
+    }
+
+    fn update_increment(&mut self) {
+        self.increment = self.frequency() / self.sample_rate;
+    }
+
+    /// Xorshift, mapped to -1..1. Good enough for a sample-and-hold and safe on the audio thread.
+    fn random(&mut self) -> f32 {
+        self.random_state ^= self.random_state << 13;
+        self.random_state ^= self.random_state >> 17;
+        self.random_state ^= self.random_state << 5;
+        self.random_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A quarter of a cycle per sample, so four samples land on phases 0, 0.25, 0.5 and 0.75.
+    fn quarter_steps(waveform: Waveform) -> Vec<f32> {
+        let mut lfo = Lfo::new(1.0);
+        lfo.waveform = waveform;
+        lfo.prepare(4.0, 0.0);
+        (0..4).map(|_| lfo.next(0)).collect()
+    }
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        for (actual, expected) in actual.iter().zip(expected) {
+            assert!((actual - expected).abs() < 1e-6, "{:?}", actual);
+        }
+    }
+
+    #[test]
+    fn waveforms_at_known_phases() {
+        assert_close(&quarter_steps(Waveform::Sine), &[0.0, 1.0, 0.0, -1.0]);
+        assert_close(&quarter_steps(Waveform::Triangle), &[-1.0, 0.0, 1.0, 0.0]);
+        assert_close(&quarter_steps(Waveform::Square), &[1.0, 1.0, -1.0, -1.0]);
+        assert_close(&quarter_steps(Waveform::SawUp), &[-1.0, -0.5, 0.0, 0.5]);
+        assert_close(&quarter_steps(Waveform::SawDown), &[1.0, 0.5, 0.0, -0.5]);
+    }
+
+    #[test]
+    fn sample_and_hold_changes_only_once_a_cycle() {
+        let mut lfo = Lfo::new(1.0);
+        lfo.waveform = Waveform::SampleAndHold;
+        lfo.prepare(4.0, 0.0);
+
+        let values: Vec<f32> = (0..12).map(|_| lfo.next(0)).collect();
+        for cycle in values[4..].chunks(4) {
+            assert!(cycle.iter().all(|value| *value == cycle[0]));
+            assert!((-1.0..=1.0).contains(&cycle[0]));
+        }
+        assert_ne!(values[4], values[8]);
+    }
+
+    #[test]
+    fn spread_offsets_each_channel() {
+        let mut lfo = Lfo::new(1.0);
+        lfo.waveform = Waveform::SawUp;
+        lfo.prepare(4.0, 0.25);
+
+        assert_eq!(lfo.next(0), -1.0);
+        assert_eq!(lfo.next(1), -0.5);
+    }
+
+    #[test]
+    fn a_synced_rate_follows_the_note_length() {
+        let mut lfo = Lfo::new(3.0);
+        assert_eq!(lfo.frequency(), 3.0);
+
+        // A quarter note at 120 BPM lasts 500 ms.
+        lfo.set_parameter("division", 3.0);
+        assert_eq!(lfo.frequency(), 2.0);
+
+        lfo.set_parameter("tempo", 60.0);
+        assert_eq!(lfo.frequency(), 1.0);
+
+        lfo.prepare(1000.0, 0.0);
+        assert_eq!(lfo.increment, 0.001);
+    }
+}
@@ -1,5 +1,17 @@
+pub mod audio_buffer;
 pub mod audio_device_manager;
 pub mod audio_pipeline;
 pub mod audio_stream_manager;
+pub mod convolver;
+pub mod delay_line;
+pub mod dsp;
+pub mod lfo;
+pub mod pipeline_controller;
+pub mod pipeline_history;
+pub mod processor_registry;
 pub mod processor_trait;
 pub mod processors;
+pub mod resampler;
+pub mod routing;
+pub mod stream_telemetry;
+pub mod tap_tempo;
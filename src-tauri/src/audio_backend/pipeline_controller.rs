@@ -0,0 +1,907 @@
+use std::{collections::HashMap, time::Instant};
+
+use anyhow::{anyhow, Error};
+use ringbuf::{HeapConsumer, HeapProducer, HeapRb};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    audio_buffer::MAX_CHANNELS,
+    audio_pipeline::{
+        AudioPipeline, Chain, PipelineCommand, PipelineGarbage, ProcessorNode, SlotState,
+        MAX_PROCESSORS,
+    },
+    pipeline_history::{HistoryView, PipelineHistory},
+    processor_trait::{ParameterError, Processor, ProcessorHashMapValue},
+    routing::{self, RouteNode, RouteStep, RoutingError},
+    tap_tempo::{self, TapTempo, TappedParameter},
+};
+
+const COMMAND_QUEUE_SIZE: usize = 256;
+
+// Length of the fade out and back in around chain edits, and of bypass and mix changes.
+const DECLICK_MS: f32 = 5.0;
+
+// Length of the crossfade when the whole chain is swapped, as on A/B switches and preset loads.
+const CROSSFADE_MS: f32 = 50.0;
+
+/// Identifies one processor in the chain for as long as it stays there, so the chain can hold
+/// several processors of the same kind.
+pub type ProcessorId = u32;
+
+pub struct ProcessorSlot {
+    pub id: ProcessorId,
+    pub processor: Box<dyn Processor>,
+    pub state: SlotState,
+    // Where the realtime copy lives in the `AudioPipeline` pool.
+    pool_index: usize,
+}
+
+/// A processor as stored in a preset. `id` only links it to the saved routing.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProcessorState {
+    pub id: ProcessorId,
+    pub name: String,
+    pub values: HashMap<String, f32>,
+    pub slot: SlotState,
+    #[serde(default)]
+    pub files: HashMap<String, String>,
+}
+
+/// Everything needed to rebuild the chain.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PipelineState {
+    pub processors: Vec<ProcessorState>,
+    pub routing: Vec<RouteNode>,
+}
+
+/// One of the two chains kept side by side for comparison.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum AbSlot {
+    A,
+    B,
+}
+
+impl AbSlot {
+    fn other(self) -> Self {
+        match self {
+            AbSlot::A => AbSlot::B,
+            AbSlot::B => AbSlot::A,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            AbSlot::A => "A",
+            AbSlot::B => "B",
+        }
+    }
+}
+
+/// Both A/B slots. The active one always holds the chain as it is now.
+#[derive(Serialize, Clone)]
+pub struct AbView {
+    pub active: AbSlot,
+    pub a: PipelineState,
+    pub b: PipelineState,
+}
+
+/// The chain in the slot that isn't playing, with its own undo history.
+struct StoredChain {
+    state: PipelineState,
+    history: PipelineHistory,
+}
+
+/// The chain as sent to the frontend.
+#[derive(Serialize, Clone)]
+pub struct PipelineView {
+    pub processors: Vec<HashMap<String, ProcessorHashMapValue>>,
+    pub routing: Vec<RouteNode>,
+    pub history: HistoryView,
+    pub ab: AbView,
+}
+
+/// The UI side of the processor chain. Tauri commands edit the chain here, and every edit is
+/// forwarded to the running `AudioPipeline` through a lock-free queue. `processors` holds every
+/// processor in the chain; `routing` decides the order they run in.
+pub struct PipelineController {
+    pub processors: Vec<ProcessorSlot>,
+    pub routing: Vec<RouteNode>,
+    next_id: ProcessorId,
+    // Set by every edit, so the chain can be saved once it settles.
+    changed: bool,
+    history: PipelineHistory,
+    tap_tempo: TapTempo,
+    active_slot: AbSlot,
+    // Empty until the first switch, which starts the other slot as a copy of the active one.
+    inactive: Option<StoredChain>,
+    sample_rate: f32,
+    max_block_size: usize,
+    commands: Option<HeapProducer<PipelineCommand>>,
+    garbage: Option<HeapConsumer<PipelineGarbage>>,
+    // Set when an edit didn't fit in the command queue. Until the whole chain has been resent,
+    // the audio thread isn't playing what is here, so further edits wait for that resend.
+    out_of_sync: bool,
+}
+
+impl PipelineController {
+    pub fn new() -> Self {
+        PipelineController {
+            processors: Vec::new(),
+            routing: Vec::new(),
+            next_id: 1,
+            changed: false,
+            history: PipelineHistory::new(PipelineState {
+                processors: Vec::new(),
+                routing: Vec::new(),
+            }),
+            tap_tempo: TapTempo::new(),
+            active_slot: AbSlot::A,
+            inactive: None,
+            sample_rate: 44_100.0,
+            max_block_size: 1024,
+            commands: None,
+            garbage: None,
+            out_of_sync: false,
+        }
+    }
+
+    /// Builds a realtime copy of the chain for a newly started output stream.
+    pub fn create_audio_pipeline(
+        &mut self,
+        sample_rate: f32,
+        max_block_size: usize,
+    ) -> AudioPipeline {
+        self.sample_rate = sample_rate;
+        self.max_block_size = max_block_size;
+
+        let (commands_producer, commands_consumer) =
+            HeapRb::<PipelineCommand>::new(COMMAND_QUEUE_SIZE).split();
+        let (garbage_producer, garbage_consumer) =
+            HeapRb::<PipelineGarbage>::new(COMMAND_QUEUE_SIZE).split();
+
+        self.commands = Some(commands_producer);
+        self.garbage = Some(garbage_consumer);
+        self.out_of_sync = false;
+
+        AudioPipeline::new(
+            self.realtime_chain(),
+            max_block_size,
+            self.fade_frames(),
+            self.ms_to_frames(CROSSFADE_MS),
+            commands_consumer,
+            garbage_producer,
+        )
+    }
+
+    /// Stops forwarding edits once the output stream has been dropped.
+    pub fn disconnect_audio_pipeline(&mut self) {
+        self.commands = None;
+        self.garbage = None;
+        self.out_of_sync = false;
+    }
+
+    pub fn add_processor(&mut self, processor: Box<dyn Processor>) -> Result<ProcessorId, Error> {
+        self.insert_processor(self.routing.len(), processor)
+    }
+
+    /// Inserts into the main chain before `index`, or at the end if `index` is past it.
+    pub fn insert_processor(
+        &mut self,
+        index: usize,
+        processor: Box<dyn Processor>,
+    ) -> Result<ProcessorId, Error> {
+        let id = self.push_slot(processor, SlotState::new(), None)?;
+
+        let index = index.min(self.routing.len());
+        self.routing.insert(index, RouteNode::Processor { id });
+        self.send_route();
+
+        Ok(id)
+    }
+
+    /// Moves a processor to `index` in the main chain, or to the end if `index` is past it.
+    pub fn move_processor(&mut self, id: ProcessorId, index: usize) -> Result<(), ParameterError> {
+        self.position(id)?;
+
+        routing::remove(&mut self.routing, id);
+        let index = index.min(self.routing.len());
+        self.routing.insert(index, RouteNode::Processor { id });
+        self.send_route();
+
+        Ok(())
+    }
+
+    pub fn swap_processors(
+        &mut self,
+        first: ProcessorId,
+        second: ProcessorId,
+    ) -> Result<(), ParameterError> {
+        self.position(first)?;
+        self.position(second)?;
+
+        routing::swap(&mut self.routing, first, second);
+        self.send_route();
+
+        Ok(())
+    }
+
+    pub fn remove_processor(&mut self, id: ProcessorId) -> Result<(), ParameterError> {
+        let index = self.position(id)?;
+        let slot = self.processors.remove(index);
+
+        // Unrouted first, so the audio thread never runs an empty slot.
+        routing::remove(&mut self.routing, id);
+        self.send_route();
+        self.send(PipelineCommand::RemoveProcessor(slot.pool_index));
+
+        Ok(())
+    }
+
+    /// Replaces the routing. It must place every processor in the chain exactly once.
+    pub fn set_routing(&mut self, routing: Vec<RouteNode>) -> Result<(), RoutingError> {
+        let ids: Vec<ProcessorId> = self.processors.iter().map(|slot| slot.id).collect();
+        routing::validate(&routing, &ids)?;
+
+        self.routing = routing;
+        self.send_route();
+
+        Ok(())
+    }
+
+    pub fn update_processor_values(
+        &mut self,
+        id: ProcessorId,
+        values: &HashMap<String, f32>,
+    ) -> Result<(), ParameterError> {
+        let index = self.position(id)?;
+        let processor = &self.processors[index].processor;
+        let mut updates = Vec::with_capacity(values.len());
+        for (name, value) in values {
+            updates.push((processor.validate_parameter(name, *value)?.name, *value));
+        }
+
+        let pool_index = self.processors[index].pool_index;
+        for (name, value) in updates {
+            self.processors[index]
+                .processor
+                .set_parameter(name, value)?;
+            self.send(PipelineCommand::SetParameter(pool_index, name, value));
+        }
+
+        Ok(())
+    }
+
+    pub fn set_processor_parameter(
+        &mut self,
+        id: ProcessorId,
+        name: &str,
+        value: f32,
+    ) -> Result<(), ParameterError> {
+        let index = self.position(id)?;
+        let slot = &mut self.processors[index];
+        let name = slot.processor.validate_parameter(name, value)?.name;
+
+        slot.processor.set_parameter(name, value)?;
+        let pool_index = slot.pool_index;
+        self.send(PipelineCommand::SetParameter(pool_index, name, value));
+
+        Ok(())
+    }
+
+    /// Sets a processor's `tempo` from taps on its tap button. While its `division` is off, the
+    /// tap goes to the parameter it can be heard on instead, a delay's `time` or a modulation
+    /// effect's `rate`. Returns the parameter that changed once there have been enough taps to
+    /// tell.
+    pub fn tap_tempo(
+        &mut self,
+        id: ProcessorId,
+    ) -> Result<Option<TappedParameter>, ParameterError> {
+        let index = self.position(id)?;
+        let processor = &self.processors[index].processor;
+        let descriptor = processor
+            .parameters()
+            .iter()
+            .find(|descriptor| descriptor.name == "tempo")
+            .ok_or_else(|| ParameterError::UnknownParameter {
+                processor: processor.get_name().to_string(),
+                name: "tempo".to_string(),
+            })?;
+        let synced = details(processor.as_ref())
+            .get("division")
+            .is_some_and(|division| *division > 0.0);
+        let parameters = processor.parameters();
+
+        let Some(tempo) = self.tap_tempo.tap(id, Instant::now()) else {
+            return Ok(None);
+        };
+        let tempo = descriptor.clamp(tempo);
+        self.set_processor_parameter(id, descriptor.name, tempo)?;
+
+        let tapped = match tap_tempo::unsynced_parameter(parameters, tempo) {
+            Some(tapped) if !synced => {
+                self.set_processor_parameter(id, tapped.name, tapped.value)?;
+                tapped
+            }
+            _ => TappedParameter {
+                name: descriptor.name,
+                value: tempo,
+            },
+        };
+
+        Ok(Some(tapped))
+    }
+
+    /// Loads a file into a processor, such as a cabinet's impulse response, and swaps in a new
+    /// realtime copy with it.
+    pub fn load_processor_file(
+        &mut self,
+        id: ProcessorId,
+        name: &str,
+        path: &str,
+    ) -> Result<(), Error> {
+        let index = self.position(id)?;
+        self.processors[index].processor.load_file(name, path)?;
+
+        let copy = self.realtime_copy(&self.processors[index]);
+        let pool_index = self.processors[index].pool_index;
+        self.send(PipelineCommand::ReplaceProcessor(pool_index, copy));
+
+        Ok(())
+    }
+
+    pub fn set_processor_bypass(
+        &mut self,
+        id: ProcessorId,
+        bypassed: bool,
+    ) -> Result<(), ParameterError> {
+        let index = self.position(id)?;
+        let slot = &mut self.processors[index];
+        slot.state.bypassed = bypassed;
+
+        let (pool_index, state) = (slot.pool_index, slot.state);
+        self.send(PipelineCommand::SetSlotState(pool_index, state));
+
+        Ok(())
+    }
+
+    pub fn set_processor_mix(&mut self, id: ProcessorId, mix: f32) -> Result<(), ParameterError> {
+        if !(0.0..=1.0).contains(&mix) {
+            return Err(ParameterError::OutOfRange {
+                name: "mix".to_string(),
+                value: mix,
+                min: 0.0,
+                max: 1.0,
+            });
+        }
+
+        let index = self.position(id)?;
+        let slot = &mut self.processors[index];
+        slot.state.mix = mix;
+
+        let (pool_index, state) = (slot.pool_index, slot.state);
+        self.send(PipelineCommand::SetSlotState(pool_index, state));
+
+        Ok(())
+    }
+
+    pub fn state(&self) -> PipelineState {
+        let processors = self
+            .processors
+            .iter()
+            .map(|slot| ProcessorState {
+                id: slot.id,
+                name: slot.processor.get_name().to_string(),
+                values: details(slot.processor.as_ref()),
+                slot: slot.state,
+                files: slot.processor.files(),
+            })
+            .collect();
+
+        PipelineState {
+            processors,
+            routing: self.routing.clone(),
+        }
+    }
+
+    /// Replaces the chain with the one in `state`, using `build` to create each processor.
+    /// Saved IDs are kept where possible. Processors that can't be built are left out, and if
+    /// the saved routing no longer fits, the rest run in series. The audio thread crossfades
+    /// from the old chain to the new one.
+    pub fn load_state(
+        &mut self,
+        state: &PipelineState,
+        build: impl Fn(&ProcessorState) -> Result<Box<dyn Processor>, Error>,
+    ) {
+        // The chain is rebuilt without forwarding each edit, then sent over in one piece.
+        let commands = self.commands.take();
+        self.rebuild(state, build);
+        self.commands = commands;
+
+        // The new chain also carries any edits that were dropped.
+        self.out_of_sync = false;
+        let chain = self.realtime_chain();
+        self.send(PipelineCommand::ReplaceChain(Box::new(chain)));
+    }
+
+    fn rebuild(
+        &mut self,
+        state: &PipelineState,
+        build: impl Fn(&ProcessorState) -> Result<Box<dyn Processor>, Error>,
+    ) {
+        self.clear();
+
+        let mut ids = HashMap::new();
+        for processor_state in &state.processors {
+            match build(processor_state).and_then(|processor| {
+                self.push_slot(processor, processor_state.slot, Some(processor_state.id))
+            }) {
+                Ok(id) => {
+                    ids.insert(processor_state.id, id);
+                    self.routing.push(RouteNode::Processor { id });
+                }
+                Err(err) => eprintln!("Failed to load {}: {:?}", processor_state.name, err),
+            }
+        }
+
+        let mut routing = state.routing.clone();
+        routing::remap(&mut routing, &ids);
+        if let Err(err) = self.set_routing(routing) {
+            eprintln!("Saved routing is invalid, running in series: {}", err);
+            self.send_route();
+        }
+    }
+
+    /// How far the chain delays the signal, summed over its processors.
+    pub fn latency_ms(&self) -> f32 {
+        self.processors
+            .iter()
+            .map(|slot| slot.processor.latency_ms(self.sample_rate))
+            .sum()
+    }
+
+    pub fn view(&self) -> PipelineView {
+        PipelineView {
+            processors: self.to_hash_maps(),
+            routing: self.routing.clone(),
+            history: self.history.view(),
+            ab: self.ab(),
+        }
+    }
+
+    pub fn ab(&self) -> AbView {
+        let active = self.state();
+        let inactive = match &self.inactive {
+            Some(stored) => stored.state.clone(),
+            None => active.clone(),
+        };
+        let (a, b) = match self.active_slot {
+            AbSlot::A => (active, inactive),
+            AbSlot::B => (inactive, active),
+        };
+
+        AbView {
+            active: self.active_slot,
+            a,
+            b,
+        }
+    }
+
+    /// Switches to the other A/B slot. Each slot keeps its own undo history.
+    pub fn switch_ab_slot(
+        &mut self,
+        build: impl Fn(&ProcessorState) -> Result<Box<dyn Processor>, Error>,
+    ) {
+        let current = self.state();
+        let other = self.inactive.take().unwrap_or_else(|| StoredChain {
+            state: current.clone(),
+            history: PipelineHistory::new(current.clone()),
+        });
+
+        let history = std::mem::replace(&mut self.history, other.history);
+        self.inactive = Some(StoredChain {
+            state: current,
+            history,
+        });
+        self.active_slot = self.active_slot.other();
+        self.load_state(&other.state, build);
+    }
+
+    /// Overwrites the other A/B slot with the chain as it is now. This can be undone from the
+    /// other slot.
+    pub fn copy_to_other_ab_slot(&mut self) {
+        let state = self.state();
+        let label = format!("Copy from {}", self.active_slot.label());
+
+        match &mut self.inactive {
+            Some(stored) => {
+                stored.history.commit(&label, None, state.clone());
+                stored.state = state;
+            }
+            None => {
+                self.inactive = Some(StoredChain {
+                    history: PipelineHistory::new(state.clone()),
+                    state,
+                })
+            }
+        }
+    }
+
+    /// Records the chain as it is now as one undoable step, labelled with the edit that led here.
+    pub fn commit(&mut self, label: &str) {
+        let state = self.state();
+        self.history.commit(label, None, state);
+    }
+
+    /// Like `commit`, but merges repeated edits of one parameter, like dragging a knob, into
+    /// one step.
+    pub fn commit_edit(&mut self, label: &str, id: ProcessorId, parameter: &str) {
+        let state = self.state();
+        self.history
+            .commit(label, Some((id, parameter.to_string())), state);
+    }
+
+    /// Starts the history over from the chain as it is now.
+    pub fn reset_history(&mut self) {
+        self.history = PipelineHistory::new(self.state());
+    }
+
+    pub fn history(&self) -> HistoryView {
+        self.history.view()
+    }
+
+    /// Returns false if there is nothing to undo.
+    pub fn undo(
+        &mut self,
+        build: impl Fn(&ProcessorState) -> Result<Box<dyn Processor>, Error>,
+    ) -> bool {
+        match self.history.undo() {
+            Some(state) => {
+                self.load_state(&state, build);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns false if there is nothing to redo.
+    pub fn redo(
+        &mut self,
+        build: impl Fn(&ProcessorState) -> Result<Box<dyn Processor>, Error>,
+    ) -> bool {
+        match self.history.redo() {
+            Some(state) => {
+                self.load_state(&state, build);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every processor in the chain, with its instance ID under `id`.
+    pub fn to_hash_maps(&self) -> Vec<HashMap<String, ProcessorHashMapValue>> {
+        self.processors
+            .iter()
+            .map(|slot| {
+                let mut processor_hash_map = slot.processor.to_hash_map();
+                processor_hash_map.insert("id".to_string(), ProcessorHashMapValue::Id(slot.id));
+                processor_hash_map.insert(
+                    "bypassed".to_string(),
+                    ProcessorHashMapValue::Bool(slot.state.bypassed),
+                );
+                processor_hash_map.insert(
+                    "mix".to_string(),
+                    ProcessorHashMapValue::Number(slot.state.mix),
+                );
+                processor_hash_map.insert(
+                    "latency_ms".to_string(),
+                    ProcessorHashMapValue::Number(slot.processor.latency_ms(self.sample_rate)),
+                );
+                processor_hash_map
+            })
+            .collect()
+    }
+
+    /// Meter readings of every processor that has any.
+    pub fn meters(&self) -> HashMap<ProcessorId, HashMap<String, f32>> {
+        self.processors
+            .iter()
+            .map(|slot| (slot.id, slot.processor.meters()))
+            .filter(|(_, meters)| !meters.is_empty())
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.routing.clear();
+        self.send_route();
+
+        while let Some(slot) = self.processors.pop() {
+            self.send(PipelineCommand::RemoveProcessor(slot.pool_index));
+        }
+    }
+
+    /// Frees what the audio thread has handed back, and resends the whole chain if edits were
+    /// dropped on a full queue, once there is room. Called on a timer as well as on every edit,
+    /// so the garbage queue doesn't fill up while nothing is being edited.
+    pub fn resync(&mut self) {
+        if let Some(garbage) = &mut self.garbage {
+            garbage.clear();
+        }
+
+        let has_room = matches!(&self.commands, Some(commands) if !commands.is_full());
+        if !self.out_of_sync || !has_room {
+            return;
+        }
+
+        let chain = self.realtime_chain();
+        if let Some(commands) = &mut self.commands {
+            if commands
+                .push(PipelineCommand::ReplaceChain(Box::new(chain)))
+                .is_ok()
+            {
+                self.out_of_sync = false;
+            }
+        }
+    }
+
+    /// Adds a processor to the pool without routing it, under `id` if it is still free.
+    fn push_slot(
+        &mut self,
+        processor: Box<dyn Processor>,
+        state: SlotState,
+        id: Option<ProcessorId>,
+    ) -> Result<ProcessorId, Error> {
+        let pool_index = (0..MAX_PROCESSORS)
+            .find(|pool_index| {
+                !self
+                    .processors
+                    .iter()
+                    .any(|slot| slot.pool_index == *pool_index)
+            })
+            .ok_or(anyhow!("Pipeline is full"))?;
+
+        let id = match id {
+            Some(id) if self.position(id).is_err() => {
+                self.next_id = self.next_id.max(id + 1);
+                id
+            }
+            _ => {
+                self.next_id += 1;
+                self.next_id - 1
+            }
+        };
+
+        let slot = ProcessorSlot {
+            id,
+            processor,
+            state,
+            pool_index,
+        };
+        let copy = self.realtime_copy(&slot);
+        self.send(PipelineCommand::AddProcessor(pool_index, copy));
+        self.processors.push(slot);
+
+        Ok(id)
+    }
+
+    fn position(&self, id: ProcessorId) -> Result<usize, ParameterError> {
+        self.processors
+            .iter()
+            .position(|slot| slot.id == id)
+            .ok_or(ParameterError::UnknownProcessor { id })
+    }
+
+    fn realtime_chain(&self) -> Chain {
+        let mut processors: Vec<_> = (0..MAX_PROCESSORS).map(|_| None).collect();
+        for slot in &self.processors {
+            processors[slot.pool_index] = Some(self.realtime_copy(slot));
+        }
+
+        Chain::new(processors, self.compile_route())
+    }
+
+    fn realtime_copy(&self, slot: &ProcessorSlot) -> ProcessorNode {
+        let mut copy = slot.processor.clone_box();
+        copy.prepare(self.sample_rate, MAX_CHANNELS, self.max_block_size);
+        ProcessorNode::new(copy, slot.state, self.fade_frames())
+    }
+
+    fn compile_route(&self) -> Vec<RouteStep> {
+        let pool_index = |id| {
+            self.processors
+                .iter()
+                .find(|slot| slot.id == id)
+                .map(|slot| slot.pool_index)
+        };
+
+        let mut steps = Vec::new();
+        routing::compile(&self.routing, &pool_index, &mut steps);
+        steps
+    }
+
+    fn send_route(&mut self) {
+        let route = self.compile_route();
+        self.send(PipelineCommand::SetRoute(route));
+    }
+
+    fn fade_frames(&self) -> usize {
+        self.ms_to_frames(DECLICK_MS)
+    }
+
+    fn ms_to_frames(&self, ms: f32) -> usize {
+        (self.sample_rate * ms / 1_000.0) as usize
+    }
+
+    /// Returns whether the chain has been edited since the last call.
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    fn send(&mut self, command: PipelineCommand) {
+        self.changed = true;
+        self.resync();
+        if self.out_of_sync {
+            // The edit is already in the chain the resync will send.
+            return;
+        }
+
+        if let Some(commands) = &mut self.commands {
+            if commands.push(command).is_err() {
+                eprintln!("Pipeline command queue is full, resending the chain once it drains");
+                self.out_of_sync = true;
+            }
+        }
+    }
+}
+
+impl Default for PipelineController {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A processor's parameter values, as its `to_hash_map` lists them.
+fn details(processor: &dyn Processor) -> HashMap<String, f32> {
+    match processor.to_hash_map().remove("details") {
+        Some(ProcessorHashMapValue::Map(values)) => values,
+        _ => HashMap::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_backend::processors::{amplifier::Amplifier, chorus::Chorus, delay::Delay};
+
+    const SAMPLE_RATE: f32 = 44_100.0;
+    const BLOCK_SIZE: usize = 512;
+
+    fn amplifier_chain() -> (PipelineController, ProcessorId) {
+        let mut controller = PipelineController::new();
+        let id = controller
+            .add_processor(Box::new(Amplifier::new()))
+            .unwrap();
+        (controller, id)
+    }
+
+    fn run(pipeline: &mut AudioPipeline, blocks: usize) -> Vec<f32> {
+        let input = [0.01; BLOCK_SIZE];
+        let mut output = [0.0; BLOCK_SIZE];
+        for _ in 0..blocks {
+            pipeline.process_block(&input, &mut output, 1);
+        }
+        output.to_vec()
+    }
+
+    #[test]
+    fn resends_the_chain_after_a_full_queue() {
+        let (mut controller, id) = amplifier_chain();
+        let mut pipeline = controller.create_audio_pipeline(SAMPLE_RATE, BLOCK_SIZE);
+
+        // More edits than the queue holds, with the audio thread not keeping up.
+        for step in 0..=COMMAND_QUEUE_SIZE + 10 {
+            let volume = step as f32 / (COMMAND_QUEUE_SIZE + 10) as f32;
+            controller
+                .set_processor_parameter(id, "volume", volume)
+                .unwrap();
+        }
+        assert!(controller.out_of_sync);
+
+        run(&mut pipeline, 1);
+        controller.resync();
+        assert!(!controller.out_of_sync);
+
+        let (mut reference, reference_id) = amplifier_chain();
+        reference
+            .set_processor_parameter(reference_id, "volume", 1.0)
+            .unwrap();
+        let mut reference_pipeline = reference.create_audio_pipeline(SAMPLE_RATE, BLOCK_SIZE);
+
+        // Long enough for the crossfade to the resent chain to finish.
+        let output = run(&mut pipeline, 20);
+        let expected = run(&mut reference_pipeline, 20);
+        for (output, expected) in output.iter().zip(&expected) {
+            assert!(
+                (output - expected).abs() < 1e-4,
+                "{} != {}",
+                output,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn resync_frees_what_the_audio_thread_hands_back() {
+        let (mut controller, id) = amplifier_chain();
+        let mut pipeline = controller.create_audio_pipeline(SAMPLE_RATE, BLOCK_SIZE);
+
+        controller.remove_processor(id).unwrap();
+        run(&mut pipeline, 1);
+        assert!(!controller.garbage.as_ref().unwrap().is_empty());
+
+        controller.resync();
+        assert!(controller.garbage.as_ref().unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_in_sync_while_the_queue_has_room() {
+        let (mut controller, id) = amplifier_chain();
+        let mut pipeline = controller.create_audio_pipeline(SAMPLE_RATE, BLOCK_SIZE);
+
+        for _ in 0..4 {
+            for _ in 0..COMMAND_QUEUE_SIZE / 2 {
+                controller
+                    .set_processor_parameter(id, "volume", 0.5)
+                    .unwrap();
+            }
+            run(&mut pipeline, 1);
+        }
+        assert!(!controller.out_of_sync);
+    }
+
+    #[test]
+    fn taps_set_the_time_of_an_unsynced_delay() {
+        let mut controller = PipelineController::new();
+        let id = controller.add_processor(Box::new(Delay::new())).unwrap();
+
+        // Taps this close together clamp to the fastest tempo, 300 BPM.
+        assert_eq!(controller.tap_tempo(id).unwrap(), None);
+        let tapped = controller.tap_tempo(id).unwrap().unwrap();
+        assert_eq!(tapped.name, "time");
+        assert_eq!(tapped.value, 200.0);
+
+        let values = details(controller.processors[0].processor.as_ref());
+        assert_eq!(values["time"], 200.0);
+        assert_eq!(values["tempo"], 300.0);
+    }
+
+    #[test]
+    fn taps_set_the_tempo_of_a_synced_delay() {
+        let mut controller = PipelineController::new();
+        let id = controller.add_processor(Box::new(Delay::new())).unwrap();
+        controller
+            .set_processor_parameter(id, "division", 3.0)
+            .unwrap();
+        let time = details(controller.processors[0].processor.as_ref())["time"];
+
+        controller.tap_tempo(id).unwrap();
+        let tapped = controller.tap_tempo(id).unwrap().unwrap();
+        assert_eq!(tapped.name, "tempo");
+
+        let values = details(controller.processors[0].processor.as_ref());
+        assert_eq!(values["time"], time);
+    }
+
+    #[test]
+    fn taps_set_the_rate_of_unsynced_modulation() {
+        let mut controller = PipelineController::new();
+        let id = controller.add_processor(Box::new(Chorus::new())).unwrap();
+
+        controller.tap_tempo(id).unwrap();
+        let tapped = controller.tap_tempo(id).unwrap().unwrap();
+        assert_eq!(tapped.name, "rate");
+
+        let values = details(controller.processors[0].processor.as_ref());
+        assert_eq!(values["rate"], tapped.value);
+    }
+}
@@ -0,0 +1,206 @@
+use serde::Serialize;
+
+use super::pipeline_controller::{PipelineState, ProcessorId};
+
+// Oldest steps are dropped past this.
+const MAX_HISTORY: usize = 100;
+
+/// The labels of the steps that can be undone and redone, most recent last.
+#[derive(Serialize, Clone)]
+pub struct HistoryView {
+    pub undo: Vec<String>,
+    pub redo: Vec<String>,
+}
+
+struct HistoryEntry {
+    label: String,
+    state: PipelineState,
+}
+
+/// Snapshots of the chain between edits. Each entry holds the state before the edit it is
+/// labelled with.
+pub struct PipelineHistory {
+    undo: Vec<HistoryEntry>,
+    redo: Vec<HistoryEntry>,
+    current: PipelineState,
+    // What the last step was an edit of, if it can take more edits. Dragging a knob sends many
+    // edits to one parameter of one processor, which are merged into one step.
+    coalesce: Option<(ProcessorId, String)>,
+}
+
+impl PipelineHistory {
+    pub fn new(current: PipelineState) -> Self {
+        PipelineHistory {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            current,
+            coalesce: None,
+        }
+    }
+
+    /// Records `state` as a new step. Passing the processor and parameter that were edited merges
+    /// the step into the last one if that was an edit of the same parameter; passing `None`
+    /// always makes a step of its own.
+    pub fn commit(
+        &mut self,
+        label: &str,
+        coalesce: Option<(ProcessorId, String)>,
+        state: PipelineState,
+    ) {
+        if state == self.current {
+            return;
+        }
+
+        if coalesce.is_none() || coalesce != self.coalesce || self.undo.is_empty() {
+            let previous = std::mem::replace(&mut self.current, state);
+            self.undo.push(HistoryEntry {
+                label: label.to_string(),
+                state: previous,
+            });
+            if self.undo.len() > MAX_HISTORY {
+                self.undo.remove(0);
+            }
+        } else {
+            self.current = state;
+        }
+
+        self.redo.clear();
+        self.coalesce = coalesce;
+    }
+
+    /// Returns the state to go back to, if any.
+    pub fn undo(&mut self) -> Option<PipelineState> {
+        let entry = self.undo.pop()?;
+        let next = std::mem::replace(&mut self.current, entry.state.clone());
+        self.redo.push(HistoryEntry {
+            label: entry.label,
+            state: next,
+        });
+        self.coalesce = None;
+
+        Some(entry.state)
+    }
+
+    /// Returns the state to go forward to, if any.
+    pub fn redo(&mut self) -> Option<PipelineState> {
+        let entry = self.redo.pop()?;
+        let previous = std::mem::replace(&mut self.current, entry.state.clone());
+        self.undo.push(HistoryEntry {
+            label: entry.label,
+            state: previous,
+        });
+        self.coalesce = None;
+
+        Some(entry.state)
+    }
+
+    pub fn view(&self) -> HistoryView {
+        HistoryView {
+            undo: self.undo.iter().map(|entry| entry.label.clone()).collect(),
+            redo: self.redo.iter().map(|entry| entry.label.clone()).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_backend::routing::RouteNode;
+
+    // A chain of processors in series, one per ID.
+    fn chain(ids: &[u32]) -> PipelineState {
+        PipelineState {
+            processors: Vec::new(),
+            routing: ids
+                .iter()
+                .map(|id| RouteNode::Processor { id: *id })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn undoes_and_redoes_in_order() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        history.commit("Add amplifier", None, chain(&[1]));
+        history.commit("Add delay", None, chain(&[1, 2]));
+
+        assert_eq!(history.undo(), Some(chain(&[1])));
+        assert_eq!(history.undo(), Some(chain(&[])));
+        assert_eq!(history.undo(), None);
+
+        assert_eq!(history.redo(), Some(chain(&[1])));
+        let view = history.view();
+        assert_eq!(view.undo, ["Add amplifier"]);
+        assert_eq!(view.redo, ["Add delay"]);
+
+        assert_eq!(history.redo(), Some(chain(&[1, 2])));
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn a_new_edit_clears_redo() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        history.commit("Add amplifier", None, chain(&[1]));
+        history.undo();
+
+        history.commit("Add delay", None, chain(&[2]));
+        assert!(history.view().redo.is_empty());
+        assert_eq!(history.redo(), None);
+    }
+
+    #[test]
+    fn ignores_edits_that_change_nothing() {
+        let mut history = PipelineHistory::new(chain(&[1]));
+        history.commit("Move processor", None, chain(&[1]));
+
+        assert!(history.view().undo.is_empty());
+    }
+
+    #[test]
+    fn drops_the_oldest_steps() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        for id in 0..MAX_HISTORY as u32 + 10 {
+            history.commit(&format!("Add {}", id), None, chain(&[id]));
+        }
+
+        let view = history.view();
+        assert_eq!(view.undo.len(), MAX_HISTORY);
+        assert_eq!(view.undo[0], "Add 10");
+    }
+
+    #[test]
+    fn merges_edits_of_one_parameter() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        history.commit("Add amplifier", None, chain(&[1]));
+        for id in 2..5 {
+            history.commit("Set gain", Some((1, "gain".to_string())), chain(&[1, id]));
+        }
+
+        assert_eq!(history.view().undo, ["Add amplifier", "Set gain"]);
+        assert_eq!(history.undo(), Some(chain(&[1])));
+    }
+
+    #[test]
+    fn keeps_other_edits_apart() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        history.commit("Set gain", Some((1, "gain".to_string())), chain(&[1]));
+        history.commit("Set gain", Some((2, "gain".to_string())), chain(&[2]));
+        history.commit("Set bass", Some((2, "bass".to_string())), chain(&[3]));
+        history.commit("Move processor", None, chain(&[4]));
+        history.commit("Move processor", None, chain(&[5]));
+
+        assert_eq!(history.view().undo.len(), 5);
+    }
+
+    #[test]
+    fn undo_ends_a_merge() {
+        let mut history = PipelineHistory::new(chain(&[]));
+        history.commit("Set gain", Some((1, "gain".to_string())), chain(&[1]));
+        history.commit("Set gain", Some((1, "gain".to_string())), chain(&[2]));
+        history.undo();
+        history.commit("Set gain", Some((1, "gain".to_string())), chain(&[3]));
+
+        assert_eq!(history.view().undo.len(), 1);
+        assert_eq!(history.undo(), Some(chain(&[])));
+    }
+}
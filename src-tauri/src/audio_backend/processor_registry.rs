@@ -0,0 +1,258 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Error};
+use serde::Serialize;
+
+use super::{
+    pipeline_controller::ProcessorState,
+    processor_trait::{ParameterDescriptor, Processor},
+    processors::{
+        amplifier::{self, Amplifier},
+        cabinet::{self, Cabinet},
+        chorus::{self, Chorus},
+        compressor::{self, Compressor},
+        delay::{self, Delay},
+        flanger::{self, Flanger},
+        noise_gate::{self, NoiseGate},
+        phaser::{self, Phaser},
+        reverb::{self, Reverb},
+        screamer::{self, ScreamerPedal},
+        tremolo::{self, Tremolo},
+        vibrato::{self, Vibrato},
+    },
+};
+
+/// Groups processors in the effects list.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessorCategory {
+    Dynamics,
+    Drive,
+    Amp,
+    Cabinet,
+    Modulation,
+    Delay,
+    Reverb,
+}
+
+/// One kind of processor the chain can hold.
+pub struct ProcessorKind {
+    /// Matches the processor's `get_name`, and is what presets store.
+    pub id: &'static str,
+    pub display_name: &'static str,
+    pub category: ProcessorCategory,
+    /// Matches the processor's `parameters`, so the schema needs no instance.
+    pub parameters: &'static [ParameterDescriptor],
+    pub create: fn() -> Box<dyn Processor>,
+}
+
+impl ProcessorKind {
+    pub fn schema(&self) -> ProcessorSchema {
+        ProcessorSchema {
+            name: self.id,
+            display_name: self.display_name,
+            category: self.category,
+            parameters: self.parameters,
+        }
+    }
+}
+
+/// A processor kind as sent to the frontend and used to check the assistant's settings.
+#[derive(Serialize, Clone)]
+pub struct ProcessorSchema {
+    pub name: &'static str,
+    pub display_name: &'static str,
+    pub category: ProcessorCategory,
+    pub parameters: &'static [ParameterDescriptor],
+}
+
+/// Every processor kind, in the order they are offered.
+pub const PROCESSORS: &[ProcessorKind] = &[
+    ProcessorKind {
+        id: noise_gate::ID,
+        display_name: "Noise Gate",
+        category: ProcessorCategory::Dynamics,
+        parameters: &noise_gate::PARAMETERS,
+        create: || Box::new(NoiseGate::new()),
+    },
+    ProcessorKind {
+        id: compressor::ID,
+        display_name: "Compressor",
+        category: ProcessorCategory::Dynamics,
+        parameters: &compressor::PARAMETERS,
+        create: || Box::new(Compressor::new()),
+    },
+    ProcessorKind {
+        id: amplifier::ID,
+        display_name: "Amplifier",
+        category: ProcessorCategory::Amp,
+        parameters: &amplifier::PARAMETERS,
+        create: || Box::new(Amplifier::new()),
+    },
+    ProcessorKind {
+        id: screamer::ID,
+        display_name: "Screamer",
+        category: ProcessorCategory::Drive,
+        parameters: &screamer::PARAMETERS,
+        create: || Box::new(ScreamerPedal::new()),
+    },
+    ProcessorKind {
+        id: cabinet::ID,
+        display_name: "Cabinet",
+        category: ProcessorCategory::Cabinet,
+        parameters: &cabinet::PARAMETERS,
+        create: || Box::new(Cabinet::new()),
+    },
+    ProcessorKind {
+        id: chorus::ID,
+        display_name: "Chorus",
+        category: ProcessorCategory::Modulation,
+        parameters: &chorus::PARAMETERS,
+        create: || Box::new(Chorus::new()),
+    },
+    ProcessorKind {
+        id: flanger::ID,
+        display_name: "Flanger",
+        category: ProcessorCategory::Modulation,
+        parameters: &flanger::PARAMETERS,
+        create: || Box::new(Flanger::new()),
+    },
+    ProcessorKind {
+        id: phaser::ID,
+        display_name: "Phaser",
+        category: ProcessorCategory::Modulation,
+        parameters: &phaser::PARAMETERS,
+        create: || Box::new(Phaser::new()),
+    },
+    ProcessorKind {
+        id: tremolo::ID,
+        display_name: "Tremolo",
+        category: ProcessorCategory::Modulation,
+        parameters: &tremolo::PARAMETERS,
+        create: || Box::new(Tremolo::new()),
+    },
+    ProcessorKind {
+        id: vibrato::ID,
+        display_name: "Vibrato",
+        category: ProcessorCategory::Modulation,
+        parameters: &vibrato::PARAMETERS,
+        create: || Box::new(Vibrato::new()),
+    },
+    ProcessorKind {
+        id: delay::ID,
+        display_name: "Delay",
+        category: ProcessorCategory::Delay,
+        parameters: &delay::PARAMETERS,
+        create: || Box::new(Delay::new()),
+    },
+    ProcessorKind {
+        id: reverb::ID,
+        display_name: "Reverb",
+        category: ProcessorCategory::Reverb,
+        parameters: &reverb::PARAMETERS,
+        create: || Box::new(Reverb::new()),
+    },
+];
+
+/// Looks a processor kind up by ID. Case is ignored, as older presets saved "Screamer".
+pub fn find(id: &str) -> Option<&'static ProcessorKind> {
+    PROCESSORS
+        .iter()
+        .find(|kind| kind.id.eq_ignore_ascii_case(id))
+}
+
+/// Creates a processor by ID and applies `values`, if any.
+pub fn create(
+    id: &str,
+    values: Option<&HashMap<String, f32>>,
+) -> Result<Box<dyn Processor>, Error> {
+    let kind = find(id).ok_or(anyhow!("Processor {} not found", id))?;
+
+    let mut processor = (kind.create)();
+    if let Some(values) = values {
+        processor.update_values(values)?;
+    }
+
+    Ok(processor)
+}
+
+/// Recreates a processor as it was saved. A file that can no longer be loaded is left out
+/// rather than losing the whole processor.
+pub fn restore(state: &ProcessorState) -> Result<Box<dyn Processor>, Error> {
+    let mut processor = create(&state.name, Some(&state.values))?;
+
+    for (name, path) in &state.files {
+        if let Err(err) = processor.load_file(name, path) {
+            eprintln!("Failed to load {} from {}: {:?}", name, path, err);
+        }
+    }
+
+    Ok(processor)
+}
+
+pub fn schemas() -> Vec<ProcessorSchema> {
+    PROCESSORS.iter().map(ProcessorKind::schema).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio_backend::processor_trait::ProcessorHashMapValue;
+
+    fn values(processor: &dyn Processor) -> HashMap<String, f32> {
+        match processor.to_hash_map().remove("details") {
+            Some(ProcessorHashMapValue::Map(values)) => values,
+            _ => panic!("{} has no details", processor.get_name()),
+        }
+    }
+
+    #[test]
+    fn kinds_match_the_processors_they_create() {
+        for kind in PROCESSORS {
+            let processor = (kind.create)();
+            assert_eq!(processor.get_name(), kind.id);
+            let names = |parameters: &[ParameterDescriptor]| {
+                parameters
+                    .iter()
+                    .map(|parameter| parameter.name)
+                    .collect::<Vec<_>>()
+            };
+            assert_eq!(names(processor.parameters()), names(kind.parameters));
+        }
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        for (index, kind) in PROCESSORS.iter().enumerate() {
+            assert!(PROCESSORS[..index]
+                .iter()
+                .all(|other| !other.id.eq_ignore_ascii_case(kind.id)));
+        }
+    }
+
+    #[test]
+    fn processors_start_at_and_accept_their_defaults() {
+        for kind in PROCESSORS {
+            let mut processor = (kind.create)();
+            let defaults = values(processor.as_ref());
+            for parameter in kind.parameters {
+                assert_eq!(
+                    defaults.get(parameter.name),
+                    Some(&parameter.default),
+                    "{}.{}",
+                    kind.id,
+                    parameter.name
+                );
+                assert!(
+                    processor
+                        .set_parameter(parameter.name, parameter.default)
+                        .is_ok(),
+                    "{}.{}",
+                    kind.id,
+                    parameter.name
+                );
+            }
+            assert_eq!(values(processor.as_ref()), defaults);
+        }
+    }
+}
@@ -1,15 +1,260 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
+use anyhow::{anyhow, Error};
 use serde::Serialize;
 
+use super::audio_buffer::AudioBuffer;
+
 #[derive(Serialize, Clone)]
 pub enum ProcessorHashMapValue {
     Str(String),
     Map(HashMap<String, f32>),
+    Id(u32),
+    Bool(bool),
+    Number(f32),
+}
+
+/// How a parameter should be laid out on a knob or slider.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ParameterScale {
+    Linear,
+    Log,
+    Decibel,
+}
+
+/// What values a parameter takes. Anything but `Continuous` only takes whole numbers.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ParameterKind {
+    Continuous,
+    Stepped,
+    /// Off at 0, on at 1.
+    Toggle,
+    /// An index into `options`, which label each value from `min` up.
+    Choice {
+        options: &'static [&'static str],
+    },
+}
+
+impl ParameterKind {
+    fn is_discrete(&self) -> bool {
+        !matches!(self, ParameterKind::Continuous)
+    }
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct ParameterDescriptor {
+    pub name: &'static str,
+    pub label: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub unit: &'static str,
+    pub scale: ParameterScale,
+    pub kind: ParameterKind,
+}
+
+impl ParameterDescriptor {
+    /// The nearest value the parameter accepts.
+    pub fn clamp(&self, value: f32) -> f32 {
+        let value = value.clamp(self.min, self.max);
+        if self.kind.is_discrete() {
+            value.round()
+        } else {
+            value
+        }
+    }
+
+    pub fn validate(&self, value: f32) -> Result<f32, ParameterError> {
+        if !(value >= self.min && value <= self.max) {
+            return Err(ParameterError::OutOfRange {
+                name: self.name.to_string(),
+                value,
+                min: self.min,
+                max: self.max,
+            });
+        }
+        if self.kind.is_discrete() && value.fract() != 0.0 {
+            return Err(ParameterError::NotAWholeNumber {
+                name: self.name.to_string(),
+                value,
+            });
+        }
+
+        Ok(value)
+    }
+}
+
+/// Returned to the frontend when an update is rejected. Nothing is changed when this is returned.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ParameterError {
+    UnknownProcessor {
+        id: u32,
+    },
+    UnknownParameter {
+        processor: String,
+        name: String,
+    },
+    OutOfRange {
+        name: String,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+    NotAWholeNumber {
+        name: String,
+        value: f32,
+    },
 }
+
+impl fmt::Display for ParameterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParameterError::UnknownProcessor { id } => write!(f, "Unknown processor {}", id),
+            ParameterError::UnknownParameter { processor, name } => {
+                write!(f, "{} has no parameter {}", processor, name)
+            }
+            ParameterError::OutOfRange {
+                name,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "{} must be between {} and {}, got {}",
+                name, min, max, value
+            ),
+            ParameterError::NotAWholeNumber { name, value } => {
+                write!(f, "{} must be a whole number, got {}", name, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ParameterError {}
+
 pub trait Processor: Send + Sync {
-    fn process(&self, input: f32) -> f32;
-    fn update_values(&mut self, hash_map_values: HashMap<String, String>);
+    /// Called before streaming starts, and again whenever the stream format changes.
+    /// Blocks passed to `process_block` never hold more than `max_block_size` frames
+    /// or more than `channels` channels.
+    fn prepare(&mut self, _sample_rate: f32, _channels: usize, _max_block_size: usize) {}
+    /// Processes one sample of `channel`. Stateful processors keep separate state per channel.
+    fn process(&mut self, channel: usize, input: f32) -> f32;
+    fn process_block(&mut self, buffer: &mut AudioBuffer) {
+        for channel in 0..buffer.channels() {
+            for sample in buffer.channel_mut(channel) {
+                *sample = self.process(channel, *sample);
+            }
+        }
+    }
+    /// Processors that turn a mono signal into stereo return 2; the pipeline then
+    /// widens the buffer before calling `process_block`.
+    fn output_channels(&self) -> usize {
+        1
+    }
+    /// How far the processor delays the signal at `sample_rate`, such as a lookahead. It counts
+    /// towards the reported round trip.
+    fn latency_ms(&self, _sample_rate: f32) -> f32 {
+        0.0
+    }
+    /// Sets a single parameter. Implementations should call `validate_parameter` first.
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError>;
+    /// Validates every value before applying any, so a rejected update leaves the processor as is.
+    fn update_values(&mut self, values: &HashMap<String, f32>) -> Result<(), ParameterError> {
+        for (name, value) in values {
+            self.validate_parameter(name, *value)?;
+        }
+        for (name, value) in values {
+            self.set_parameter(name, *value)?;
+        }
+        Ok(())
+    }
+    fn validate_parameter(
+        &self,
+        name: &str,
+        value: f32,
+    ) -> Result<&'static ParameterDescriptor, ParameterError> {
+        let descriptor = self
+            .parameters()
+            .iter()
+            .find(|descriptor| descriptor.name == name)
+            .ok_or_else(|| ParameterError::UnknownParameter {
+                processor: self.get_name().to_string(),
+                name: name.to_string(),
+            })?;
+        descriptor.validate(value)?;
+        Ok(descriptor)
+    }
     fn get_name(&self) -> &'static str;
+    fn parameters(&self) -> &'static [ParameterDescriptor];
+    /// Levels the UI can poll, such as gain reduction. Called on the UI side while the realtime
+    /// copy is the one processing, so metering processors share their readings through atomics.
+    fn meters(&self) -> HashMap<String, f32> {
+        HashMap::new()
+    }
+    /// Files the processor reads its data from, such as an impulse response, by name. Saved
+    /// with the chain so they can be loaded again.
+    fn files(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
+    /// Loads a file by the name `files` gives it. Runs on the UI side, so it may block; the
+    /// realtime copy picks the data up when it is prepared.
+    fn load_file(&mut self, name: &str, _path: &str) -> Result<(), Error> {
+        Err(anyhow!("{} has no file {}", self.get_name(), name))
+    }
     fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue>;
+    fn clone_box(&self) -> Box<dyn Processor>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn descriptor(kind: ParameterKind) -> ParameterDescriptor {
+        ParameterDescriptor {
+            name: "test",
+            label: "Test",
+            min: 0.0,
+            max: 4.0,
+            default: 0.0,
+            unit: "",
+            scale: ParameterScale::Linear,
+            kind,
+        }
+    }
+
+    #[test]
+    fn discrete_kinds_reject_fractions() {
+        for kind in [
+            ParameterKind::Stepped,
+            ParameterKind::Toggle,
+            ParameterKind::Choice {
+                options: &["A", "B"],
+            },
+        ] {
+            let descriptor = descriptor(kind);
+            assert!(matches!(descriptor.validate(2.0), Ok(value) if value == 2.0));
+            assert!(matches!(
+                descriptor.validate(2.5),
+                Err(ParameterError::NotAWholeNumber { .. })
+            ));
+            assert_eq!(descriptor.clamp(2.6), 3.0);
+            assert_eq!(descriptor.clamp(7.0), 4.0);
+        }
+    }
+
+    #[test]
+    fn continuous_kinds_take_any_value_in_range() {
+        let descriptor = descriptor(ParameterKind::Continuous);
+        assert!(matches!(descriptor.validate(2.5), Ok(value) if value == 2.5));
+        assert_eq!(descriptor.clamp(2.6), 2.6);
+        assert!(matches!(
+            descriptor.validate(5.0),
+            Err(ParameterError::OutOfRange { .. })
+        ));
+        assert!(descriptor.validate(f32::NAN).is_err());
+        assert_eq!(descriptor.clamp(-1.0), 0.0);
+    }
 }
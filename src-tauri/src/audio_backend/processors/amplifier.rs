@@ -1,15 +1,75 @@
 use std::collections::HashMap;
 
-use crate::audio_backend::processor_trait::{Processor, ProcessorHashMapValue};
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+};
+
+pub const ID: &str = "amplifier";
+
+pub const PARAMETERS: [ParameterDescriptor; 4] = [
+    ParameterDescriptor {
+        name: "preamp_gain",
+        label: "Preamp Gain",
+        min: 0.0,
+        max: 100.0,
+        default: 40.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "distortion_gain",
+        label: "Distortion",
+        min: 0.0,
+        max: 100.0,
+        default: 60.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "tone",
+        label: "Tone",
+        min: 20.0,
+        max: 20_000.0,
+        default: 400.0,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "volume",
+        label: "Volume",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+];
+
+#[derive(Clone)]
 pub struct Amplifier {
     pub preamp_gain: f32,
     pub distortion_gain: f32,
     pub tone: f32,
     pub volume: f32,
+    sample_rate: f32,
+    low_pass_state: [f32; MAX_CHANNELS], // Previous output of the tone filter, per channel
 }
 
 impl Processor for Amplifier {
-    fn process(&self, sample: f32) -> f32 {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.low_pass_state = [0.0; MAX_CHANNELS];
+    }
+
+    fn process(&mut self, channel: usize, sample: f32) -> f32 {
         // Apply preamp gain
         let preamped_sample = sample * self.preamp_gain;
 
@@ -17,7 +77,7 @@ impl Processor for Amplifier {
         let distorted_sample = self.apply_distortion(preamped_sample);
 
         // Apply a simple low-pass filter to control high-frequency content
-        let filtered_sample = self.apply_low_pass_filter(distorted_sample);
+        let filtered_sample = self.apply_low_pass_filter(channel, distorted_sample);
 
         // Adjust overall volume
         let output_sample = filtered_sample * self.volume;
@@ -25,44 +85,31 @@ impl Processor for Amplifier {
         output_sample
     }
 
-    fn update_values(&mut self, hash_map_values: HashMap<String, String>) {
-        self.volume = hash_map_values
-            .get("volume")
-            .unwrap()
-            .parse::<f32>()
-            .unwrap();
-        println!(
-            "Set volume to: {:#?}",
-            hash_map_values.get("volume").unwrap()
-        );
-
-        self.preamp_gain = hash_map_values
-            .get("preamp_gain")
-            .unwrap()
-            .parse::<f32>()
-            .unwrap();
-
-        println!(
-            "Set preamp_gain to: {:#?}",
-            hash_map_values.get("preamp_gain").unwrap()
-        );
-        self.distortion_gain = hash_map_values
-            .get("distortion_gain")
-            .unwrap()
-            .parse::<f32>()
-            .unwrap();
-
-        println!(
-            "Set distortion_gain to: {:#?}",
-            hash_map_values.get("distortion_gain").unwrap()
-        );
-        self.tone = hash_map_values.get("tone").unwrap().parse::<f32>().unwrap();
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "preamp_gain" => self.preamp_gain = value,
+            "distortion_gain" => self.distortion_gain = value,
+            "tone" => self.tone = value,
+            "volume" => self.volume = value,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
 
-        println!("Set tone to: {:#?}", hash_map_values.get("tone").unwrap());
+        Ok(())
     }
 
     fn get_name(&self) -> &'static str {
-        "amplifier"
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
     }
 
     fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
@@ -85,6 +132,10 @@ impl Processor for Amplifier {
 
         processor_hash_map
     }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
 }
 
 impl Amplifier {
@@ -95,6 +146,8 @@ impl Amplifier {
             distortion_gain: 60.0, // Gain applied during distortion stage
             tone: 400.0,           // Adjust the tone for controlling high-frequency content
             volume: 0.0,           // Adjust the overall output volume
+            sample_rate: 44_100.0,
+            low_pass_state: [0.0; MAX_CHANNELS],
         }
     }
     // Apply distortion to a sample
@@ -105,15 +158,79 @@ impl Amplifier {
     }
 
     // Apply a simple low-pass filter to control high-frequency content
-    fn apply_low_pass_filter(&self, sample: f32) -> f32 {
+    fn apply_low_pass_filter(&mut self, channel: usize, sample: f32) -> f32 {
         // You can adjust the filter parameters based on your preference
-        let dt = 1.0 / 44_100.0; // Sample rate assumed to be 44.1 kHz
-        let cutoff_frequency = self.tone;
-        let alpha = cutoff_frequency * dt / (1.0 + cutoff_frequency * dt);
+        let dt = 1.0 / self.sample_rate;
+        let rc = 1.0 / (2.0 * std::f32::consts::PI * self.tone);
+        let alpha = dt / (rc + dt);
 
         // Simple one-pole low-pass filter
-        let filtered_sample = alpha * sample + (1.0 - alpha) * 0.0;
+        let state = &mut self.low_pass_state[channel];
+        *state += alpha * (sample - *state);
+
+        *state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // alpha * (1 - alpha)^n for the stream rate, which only holds if the filter remembers its
+    // last output.
+    fn check_tone_impulse_response(sample_rate: f32) {
+        let mut amplifier = Amplifier::new();
+        amplifier.prepare(sample_rate, 1, 512);
+
+        let dt = 1.0 / sample_rate;
+        let rc = 1.0 / (std::f32::consts::TAU * amplifier.tone);
+        let alpha = dt / (rc + dt);
+
+        let mut sum = 0.0;
+        for n in 0..4096 {
+            let input = if n == 0 { 1.0 } else { 0.0 };
+            let output = amplifier.apply_low_pass_filter(0, input);
+            let expected = alpha * (1.0 - alpha).powi(n);
+            assert!(
+                (output - expected).abs() < 1e-6,
+                "sample {} at {} Hz: {} != {}",
+                n,
+                sample_rate,
+                output,
+                expected
+            );
+            sum += output;
+        }
+
+        // Unity gain at DC.
+        assert!((sum - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tone_filter_impulse_response_at_44_1k() {
+        check_tone_impulse_response(44_100.0);
+    }
+
+    #[test]
+    fn tone_filter_impulse_response_at_48k() {
+        check_tone_impulse_response(48_000.0);
+    }
+
+    #[test]
+    fn tone_filter_state_is_per_instance() {
+        let mut first = Amplifier::new();
+        let mut second = Amplifier::new();
+
+        first.apply_low_pass_filter(0, 1.0);
+        assert_eq!(second.apply_low_pass_filter(0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn tone_filter_keeps_channels_apart() {
+        let mut amplifier = Amplifier::new();
+        amplifier.prepare(48_000.0, 2, 512);
 
-        filtered_sample
+        amplifier.apply_low_pass_filter(0, 1.0);
+        assert_eq!(amplifier.apply_low_pass_filter(1, 0.0), 0.0);
     }
 }
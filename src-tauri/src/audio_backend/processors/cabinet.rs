@@ -0,0 +1,316 @@
+use std::{collections::HashMap, f64::consts::PI, sync::Arc};
+
+use anyhow::{anyhow, Error};
+use hound::{SampleFormat, WavReader};
+
+use crate::audio_backend::{
+    convolver::{Convolver, PARTITION_SIZE},
+    dsp::db_to_gain,
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+};
+
+pub const ID: &str = "cabinet";
+
+pub const PARAMETERS: [ParameterDescriptor; 1] = [ParameterDescriptor {
+    name: "level",
+    label: "Level",
+    min: -24.0,
+    max: 12.0,
+    default: 0.0,
+    unit: "dB",
+    scale: ParameterScale::Decibel,
+    kind: ParameterKind::Continuous,
+}];
+
+// Cabinet responses are short; anything past this is cut off to bound the convolution cost.
+const MAX_IMPULSE_RESPONSE_MS: f32 = 500.0;
+
+// Zero crossings of the windowed sinc on each side when resampling an impulse response.
+const SINC_ZERO_CROSSINGS: usize = 32;
+
+/// A speaker cabinet, simulated by convolving with an impulse response loaded from a WAV file.
+/// Without one it passes the signal through.
+#[derive(Clone)]
+pub struct Cabinet {
+    pub level: f32,
+    // `level` as a linear gain.
+    gain: f32,
+    impulse_response: Option<Arc<ImpulseResponse>>,
+    // Built in `prepare` from the impulse response at the stream rate.
+    convolver: Option<Convolver>,
+}
+
+/// An impulse response as read from disk, mixed down to mono.
+struct ImpulseResponse {
+    path: String,
+    sample_rate: u32,
+    samples: Vec<f32>,
+}
+
+impl ImpulseResponse {
+    fn load(path: &str) -> Result<Self, Error> {
+        let mut reader = WavReader::open(path)?;
+        let spec = reader.spec();
+
+        let samples: Vec<f32> = match spec.sample_format {
+            SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+            SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 * scale))
+                    .collect::<Result<_, _>>()?
+            }
+        };
+
+        let channels = spec.channels as usize;
+        if channels == 0 || samples.len() < channels {
+            return Err(anyhow!("{} holds no samples", path));
+        }
+
+        Ok(ImpulseResponse {
+            path: path.to_string(),
+            sample_rate: spec.sample_rate,
+            samples: samples
+                .chunks(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect(),
+        })
+    }
+
+    /// The response at `sample_rate`, trimmed and scaled to unit energy so that swapping
+    /// cabinets doesn't jump in level.
+    fn prepared(&self, sample_rate: f32) -> Vec<f32> {
+        let max_length = (MAX_IMPULSE_RESPONSE_MS * 0.001 * sample_rate) as usize;
+
+        let mut samples = if self.sample_rate == sample_rate as u32 {
+            self.samples.clone()
+        } else {
+            resample(
+                &self.samples,
+                self.sample_rate as f64,
+                sample_rate as f64,
+                max_length,
+            )
+        };
+        samples.truncate(max_length);
+
+        let energy = samples
+            .iter()
+            .map(|sample| sample * sample)
+            .sum::<f32>()
+            .sqrt();
+        if energy > 0.0 {
+            for sample in &mut samples {
+                *sample /= energy;
+            }
+        }
+
+        samples
+    }
+}
+
+/// Resamples `samples` with a Blackman-windowed sinc, low-passed below the lower of the two
+/// Nyquist frequencies so that downsampling doesn't fold the top of the response back down.
+/// Too slow for the audio thread, but an impulse response is only resampled in `prepare`.
+fn resample(samples: &[f32], from_rate: f64, to_rate: f64, max_length: usize) -> Vec<f32> {
+    let step = from_rate / to_rate;
+    let cutoff = (to_rate / from_rate).min(1.0);
+    let half_width = SINC_ZERO_CROSSINGS as f64 / cutoff;
+    let length = ((samples.len() as f64 / step) as usize).min(max_length);
+
+    (0..length)
+        .map(|index| {
+            let center = index as f64 * step;
+            let first = (center - half_width).ceil().max(0.0) as usize;
+            let last = ((center + half_width).floor() as usize).min(samples.len() - 1);
+
+            (first..=last)
+                .map(|input| {
+                    let offset = input as f64 - center;
+                    let window = 0.42
+                        + 0.5 * (PI * offset / half_width).cos()
+                        + 0.08 * (2.0 * PI * offset / half_width).cos();
+                    samples[input] as f64 * cutoff * sinc(offset * cutoff) * window
+                })
+                .sum::<f64>() as f32
+        })
+        .collect()
+}
+
+fn sinc(x: f64) -> f64 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (PI * x).sin() / (PI * x)
+    }
+}
+
+impl Processor for Cabinet {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.convolver = self
+            .impulse_response
+            .as_ref()
+            .map(|impulse_response| Convolver::new(&impulse_response.prepared(sample_rate)));
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        match &mut self.convolver {
+            Some(convolver) => convolver.process(channel, input) * self.gain,
+            None => input * self.gain,
+        }
+    }
+
+    fn latency_ms(&self, sample_rate: f32) -> f32 {
+        match self.impulse_response {
+            Some(_) => PARTITION_SIZE as f32 * 1_000.0 / sample_rate,
+            None => 0.0,
+        }
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "level" => {
+                self.level = value;
+                self.gain = db_to_gain(value);
+            }
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn files(&self) -> HashMap<String, String> {
+        self.impulse_response
+            .iter()
+            .map(|impulse_response| {
+                (
+                    "impulse_response".to_string(),
+                    impulse_response.path.clone(),
+                )
+            })
+            .collect()
+    }
+
+    fn load_file(&mut self, name: &str, path: &str) -> Result<(), Error> {
+        match name {
+            "impulse_response" => {
+                self.impulse_response = Some(Arc::new(ImpulseResponse::load(path)?));
+                Ok(())
+            }
+            _ => Err(anyhow!("{} has no file {}", self.get_name(), name)),
+        }
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("level".to_string(), self.level);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+        if let Some(impulse_response) = &self.impulse_response {
+            processor_hash_map.insert(
+                "impulse_response".to_string(),
+                ProcessorHashMapValue::Str(impulse_response.path.clone()),
+            );
+        }
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Cabinet {
+    pub fn new() -> Self {
+        Cabinet {
+            level: 0.0,
+            gain: 1.0,
+            impulse_response: None,
+            convolver: None,
+        }
+    }
+}
+
+impl Default for Cabinet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hound::{WavSpec, WavWriter};
+
+    #[test]
+    fn resampling_keeps_what_fits_below_nyquist() {
+        let tone = |frequency: f64| -> Vec<f32> {
+            (0..4800)
+                .map(|n| (2.0 * PI * frequency * n as f64 / 96_000.0).sin() as f32)
+                .collect()
+        };
+        let peak = |samples: &[f32]| {
+            // Away from the ends, where the sinc runs out of input.
+            samples[200..2200]
+                .iter()
+                .fold(0.0_f32, |peak, sample| peak.max(sample.abs()))
+        };
+
+        let passed = resample(&tone(1_000.0), 96_000.0, 48_000.0, usize::MAX);
+        assert_eq!(passed.len(), 2400);
+        assert!((peak(&passed) - 1.0).abs() < 0.01);
+
+        // Would alias to 12 kHz without the low-pass.
+        let blocked = resample(&tone(36_000.0), 96_000.0, 48_000.0, usize::MAX);
+        assert!(peak(&blocked) < 0.01);
+    }
+
+    #[test]
+    fn rejects_empty_files() {
+        let path = std::env::temp_dir().join("cabinet_rejects_empty_files.wav");
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 48_000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        WavWriter::create(&path, spec).unwrap().finalize().unwrap();
+
+        let mut cabinet = Cabinet::new();
+        assert!(cabinet
+            .load_file("impulse_response", path.to_str().unwrap())
+            .is_err());
+        assert!(cabinet.files().is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
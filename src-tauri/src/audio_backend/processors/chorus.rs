@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    delay_line::DelayLine,
+    lfo::{Lfo, WAVEFORM_PARAMETER},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+    tap_tempo::{DIVISION_PARAMETER, TEMPO_PARAMETER},
+};
+
+const MAX_DELAY_MS: f32 = 30.0;
+const MAX_DEPTH_MS: f32 = 10.0;
+
+// How far through the LFO's cycle the right channel starts.
+const STEREO_SPREAD: f32 = 0.25;
+
+pub const ID: &str = "chorus";
+
+pub const PARAMETERS: [ParameterDescriptor; 7] = [
+    ParameterDescriptor {
+        name: "delay",
+        label: "Delay",
+        min: 5.0,
+        max: MAX_DELAY_MS,
+        default: 12.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "depth",
+        label: "Depth",
+        min: 0.0,
+        max: MAX_DEPTH_MS,
+        default: 2.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mix",
+        label: "Mix",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "rate",
+        label: "Rate",
+        min: 0.05,
+        max: 5.0,
+        default: 0.8,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    WAVEFORM_PARAMETER,
+    DIVISION_PARAMETER,
+    TEMPO_PARAMETER,
+];
+
+/// Mixes the signal with a copy whose delay the LFO sweeps around `delay`, so the two drift in
+/// and out of tune. It asks for a stereo buffer and runs the right channel's LFO a quarter
+/// cycle ahead.
+#[derive(Clone)]
+pub struct Chorus {
+    pub delay: f32,
+    pub depth: f32,
+    pub mix: f32,
+    lfo: Lfo,
+    sample_rate: f32,
+    channels: [DelayLine; MAX_CHANNELS],
+}
+
+impl Processor for Chorus {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.lfo.prepare(sample_rate, STEREO_SPREAD);
+
+        let length = ((MAX_DELAY_MS + MAX_DEPTH_MS) * 0.001 * sample_rate) as usize + 1;
+        self.channels = std::array::from_fn(|_| DelayLine::new(length));
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let lfo = self.lfo.next(channel);
+        let delay_samples = (self.delay + self.depth * lfo) * 0.001 * self.sample_rate;
+
+        let line = &mut self.channels[channel];
+        line.push(input);
+        let wet = line.read(delay_samples);
+
+        input + self.mix * (wet - input)
+    }
+
+    fn output_channels(&self) -> usize {
+        2
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "delay" => self.delay = value,
+            "depth" => self.depth = value,
+            "mix" => self.mix = value,
+            name if self.lfo.set_parameter(name, value) => {}
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("delay".to_string(), self.delay);
+        processor_details.insert("depth".to_string(), self.depth);
+        processor_details.insert("mix".to_string(), self.mix);
+        self.lfo.insert_details(&mut processor_details);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Chorus {
+    pub fn new() -> Self {
+        Chorus {
+            delay: 12.0,
+            depth: 2.0,
+            mix: 0.5,
+            lfo: Lfo::new(0.8),
+            sample_rate: 44_100.0,
+            // Sized in `prepare`, once the sample rate is known.
+            channels: std::array::from_fn(|_| DelayLine::new(0)),
+        }
+    }
+}
+
+impl Default for Chorus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use crate::audio_backend::{
+    audio_buffer::{AudioBuffer, MAX_CHANNELS},
+    dsp::{db_to_gain, time_coefficient},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+};
+
+pub const ID: &str = "compressor";
+
+pub const PARAMETERS: [ParameterDescriptor; 7] = [
+    ParameterDescriptor {
+        name: "threshold",
+        label: "Threshold",
+        min: -60.0,
+        max: 0.0,
+        default: -20.0,
+        unit: "dB",
+        scale: ParameterScale::Decibel,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "ratio",
+        label: "Ratio",
+        min: 1.0,
+        max: 20.0,
+        default: 4.0,
+        unit: ":1",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "attack",
+        label: "Attack",
+        min: 0.1,
+        max: 100.0,
+        default: 10.0,
+        unit: "ms",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "release",
+        label: "Release",
+        min: 10.0,
+        max: 2000.0,
+        default: 100.0,
+        unit: "ms",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "knee",
+        label: "Knee",
+        min: 0.0,
+        max: 24.0,
+        default: 6.0,
+        unit: "dB",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "makeup",
+        label: "Makeup",
+        min: 0.0,
+        max: 24.0,
+        default: 0.0,
+        unit: "dB",
+        scale: ParameterScale::Decibel,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "limiter",
+        label: "Limiter",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Toggle,
+    },
+];
+
+// How far the limiter looks ahead. The signal is always delayed by this much, so switching the
+// limiter on or off doesn't jump between a delayed and an undelayed signal.
+const LOOKAHEAD_MS: f32 = 5.0;
+
+// Keeps the level detector out of -inf on silence.
+const SILENCE_DB: f32 = -120.0;
+
+/// A feed-forward compressor with a soft knee, delayed by `LOOKAHEAD_MS`. With `limiter` on, the
+/// ratio becomes infinite and the deepest reduction is held across the delay, so the gain is
+/// already down when a peak comes out and nothing gets past the threshold plus makeup.
+#[derive(Clone)]
+pub struct Compressor {
+    pub threshold: f32,
+    pub ratio: f32,
+    pub attack: f32,
+    pub release: f32,
+    pub knee: f32,
+    pub makeup: f32,
+    pub limiter: bool,
+    sample_rate: f32,
+    // Derived from the parameters and the sample rate.
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    lookahead_coefficient: f32,
+    lookahead_samples: usize,
+    channels: [CompressorState; MAX_CHANNELS],
+    // Shared with the realtime copy, which writes the latest gain reduction in dB as f32 bits.
+    gain_reduction: Arc<AtomicU32>,
+}
+
+#[derive(Clone)]
+struct CompressorState {
+    // Smoothed gain change in dB, 0 or below.
+    reduction: f32,
+    // The deepest reduction seen within the lookahead, and how much longer to hold it.
+    held: f32,
+    hold_remaining: usize,
+    delay: Vec<f32>,
+    delay_position: usize,
+}
+
+impl CompressorState {
+    fn new(delay_samples: usize) -> Self {
+        CompressorState {
+            reduction: 0.0,
+            held: 0.0,
+            hold_remaining: 0,
+            delay: vec![0.0; delay_samples.max(1)],
+            delay_position: 0,
+        }
+    }
+}
+
+impl Processor for Compressor {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+        self.channels = std::array::from_fn(|_| CompressorState::new(self.lookahead_samples));
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let level = gain_to_db(input.abs());
+        let reduction = self.compute_gain(level) - level;
+
+        let state = &mut self.channels[channel];
+        let (target, attack_coefficient) = if self.limiter {
+            if reduction <= state.held {
+                state.held = reduction;
+                state.hold_remaining = self.lookahead_samples;
+            } else if state.hold_remaining > 0 {
+                state.hold_remaining -= 1;
+            } else {
+                state.held = reduction;
+            }
+            (state.held, self.lookahead_coefficient)
+        } else {
+            // Starts the hold afresh if the limiter is switched on.
+            state.held = reduction;
+            state.hold_remaining = 0;
+            (reduction, self.attack_coefficient)
+        };
+
+        let coefficient = if target < state.reduction {
+            attack_coefficient
+        } else {
+            self.release_coefficient
+        };
+        state.reduction = target + coefficient * (state.reduction - target);
+
+        let delayed = std::mem::replace(&mut state.delay[state.delay_position], input);
+        state.delay_position = (state.delay_position + 1) % state.delay.len();
+
+        let output = delayed * db_to_gain(state.reduction + self.makeup);
+        if self.limiter {
+            let ceiling = db_to_gain(self.threshold + self.makeup);
+            output.clamp(-ceiling, ceiling)
+        } else {
+            output
+        }
+    }
+
+    fn process_block(&mut self, buffer: &mut AudioBuffer) {
+        for channel in 0..buffer.channels() {
+            for sample in buffer.channel_mut(channel) {
+                *sample = self.process(channel, *sample);
+            }
+        }
+
+        let reduction = self.channels[..buffer.channels()]
+            .iter()
+            .map(|state| -state.reduction)
+            .fold(0.0, f32::max);
+        self.gain_reduction
+            .store(reduction.to_bits(), Ordering::Relaxed);
+    }
+
+    fn latency_ms(&self, _sample_rate: f32) -> f32 {
+        LOOKAHEAD_MS
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "threshold" => self.threshold = value,
+            "ratio" => self.ratio = value,
+            "attack" => self.attack = value,
+            "release" => self.release = value,
+            "knee" => self.knee = value,
+            "makeup" => self.makeup = value,
+            "limiter" => self.limiter = value >= 0.5,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+        self.update_coefficients();
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn meters(&self) -> HashMap<String, f32> {
+        let reduction = f32::from_bits(self.gain_reduction.load(Ordering::Relaxed));
+        HashMap::from([("gain_reduction".to_string(), reduction)])
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("threshold".to_string(), self.threshold);
+        processor_details.insert("ratio".to_string(), self.ratio);
+        processor_details.insert("attack".to_string(), self.attack);
+        processor_details.insert("release".to_string(), self.release);
+        processor_details.insert("knee".to_string(), self.knee);
+        processor_details.insert("makeup".to_string(), self.makeup);
+        processor_details.insert("limiter".to_string(), f32::from(u8::from(self.limiter)));
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Compressor {
+    pub fn new() -> Self {
+        let mut compressor = Compressor {
+            threshold: -20.0,
+            ratio: 4.0,
+            attack: 10.0,
+            release: 100.0,
+            knee: 6.0,
+            makeup: 0.0,
+            limiter: false,
+            sample_rate: 44_100.0,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+            lookahead_coefficient: 0.0,
+            lookahead_samples: 0,
+            channels: std::array::from_fn(|_| CompressorState::new(0)),
+            gain_reduction: Arc::new(AtomicU32::new(0)),
+        };
+        compressor.update_coefficients();
+        compressor
+    }
+
+    /// The output level for an input level, both in dB.
+    fn compute_gain(&self, level: f32) -> f32 {
+        let slope = if self.limiter { 0.0 } else { 1.0 / self.ratio };
+        let over = level - self.threshold;
+
+        if 2.0 * over <= -self.knee {
+            level
+        } else if 2.0 * over.abs() <= self.knee {
+            let knee_over = over + self.knee / 2.0;
+            level + (slope - 1.0) * knee_over * knee_over / (2.0 * self.knee)
+        } else {
+            self.threshold + over * slope
+        }
+    }
+
+    fn update_coefficients(&mut self) {
+        self.attack_coefficient = time_coefficient(self.attack, self.sample_rate);
+        self.release_coefficient = time_coefficient(self.release, self.sample_rate);
+        // Close to fully settled by the time the held peak leaves the delay line.
+        self.lookahead_coefficient = time_coefficient(LOOKAHEAD_MS / 5.0, self.sample_rate);
+        self.lookahead_samples = (LOOKAHEAD_MS * 0.001 * self.sample_rate) as usize;
+    }
+}
+
+impl Default for Compressor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn gain_to_db(gain: f32) -> f32 {
+    (20.0 * gain.log10()).max(SILENCE_DB)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toggling_the_limiter_keeps_the_delay() {
+        let mut compressor = Compressor::new();
+        compressor.prepare(48_000.0, 1, 64);
+        let delay = compressor.lookahead_samples;
+
+        // Well below the threshold, so only the delay shapes the output.
+        let input: Vec<f32> = (0..1000).map(|n| 0.01 * (n as f32 * 0.05).sin()).collect();
+        let mut output = Vec::new();
+        for (n, sample) in input.iter().enumerate() {
+            if n == 500 {
+                compressor.set_parameter("limiter", 1.0).unwrap();
+            }
+            output.push(compressor.process(0, *sample));
+        }
+
+        for n in delay..input.len() {
+            assert!((output[n] - input[n - delay]).abs() < 1e-6, "sample {}", n);
+        }
+    }
+}
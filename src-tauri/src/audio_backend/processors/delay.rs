@@ -0,0 +1,309 @@
+use std::{collections::HashMap, f32::consts::TAU};
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    delay_line::DelayLine,
+    lfo::Lfo,
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+    tap_tempo::{note_length_ms, DIVISION_PARAMETER, TEMPO_PARAMETER},
+};
+
+// Longest delay, so a synced whole note still fits at 60 BPM.
+const MAX_DELAY_MS: f32 = 4000.0;
+
+const MAX_MOD_DEPTH_MS: f32 = 10.0;
+
+pub const ID: &str = "delay";
+
+pub const PARAMETERS: [ParameterDescriptor; 9] = [
+    ParameterDescriptor {
+        name: "time",
+        label: "Time",
+        min: 1.0,
+        max: 2000.0,
+        default: 400.0,
+        unit: "ms",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    DIVISION_PARAMETER,
+    TEMPO_PARAMETER,
+    ParameterDescriptor {
+        name: "feedback",
+        label: "Feedback",
+        min: 0.0,
+        max: 0.95,
+        default: 0.4,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mix",
+        label: "Mix",
+        min: 0.0,
+        max: 1.0,
+        default: 0.35,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "high_cut",
+        label: "High Cut",
+        min: 500.0,
+        max: 20000.0,
+        default: 6000.0,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mod_rate",
+        label: "Mod Rate",
+        min: 0.05,
+        max: 10.0,
+        default: 0.5,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mod_depth",
+        label: "Mod Depth",
+        min: 0.0,
+        max: MAX_MOD_DEPTH_MS,
+        default: 0.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "analog",
+        label: "Analog",
+        min: 0.0,
+        max: 1.0,
+        default: 0.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Toggle,
+    },
+];
+
+// How long a change of delay time takes to glide in, rather than jumping and clicking.
+const TIME_SMOOTHING_MS: f32 = 50.0;
+
+/// An echo with filtered feedback. Each repeat passes through `high_cut`; in analog mode the
+/// repeats are also softly saturated, so they get darker and grittier as they fade. The time is
+/// either set directly or synced to `tempo`.
+#[derive(Clone)]
+pub struct Delay {
+    pub time: f32,
+    pub division: usize,
+    pub tempo: f32,
+    pub feedback: f32,
+    pub mix: f32,
+    pub high_cut: f32,
+    pub mod_depth: f32,
+    pub analog: bool,
+    // A sine at `mod_rate`, free-running whatever the delay time is synced to.
+    modulation: Lfo,
+    sample_rate: f32,
+    // Derived from the parameters and the sample rate.
+    target_samples: f32,
+    time_smoothing: f32,
+    high_cut_alpha: f32,
+    mod_depth_samples: f32,
+    channels: [DelayState; MAX_CHANNELS],
+}
+
+#[derive(Clone)]
+struct DelayState {
+    line: DelayLine,
+    // Glides towards `target_samples`.
+    delay_samples: f32,
+    filter_state: f32,
+}
+
+impl DelayState {
+    fn new(max_delay: usize) -> Self {
+        DelayState {
+            line: DelayLine::new(max_delay),
+            delay_samples: 0.0,
+            filter_state: 0.0,
+        }
+    }
+}
+
+impl Processor for Delay {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.update_coefficients();
+
+        self.modulation.prepare(sample_rate, 0.0);
+
+        let max_delay = ((MAX_DELAY_MS + MAX_MOD_DEPTH_MS) * 0.001 * sample_rate) as usize;
+        self.channels = std::array::from_fn(|_| DelayState::new(max_delay));
+        for state in &mut self.channels {
+            state.delay_samples = self.target_samples;
+        }
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let modulation = self.modulation.next(channel) * self.mod_depth_samples;
+        let state = &mut self.channels[channel];
+
+        state.delay_samples += self.time_smoothing * (self.target_samples - state.delay_samples);
+        let delayed = state.line.read(state.delay_samples + modulation);
+
+        state.filter_state += self.high_cut_alpha * (delayed - state.filter_state);
+        let mut repeat = state.filter_state * self.feedback;
+        if self.analog {
+            repeat = repeat.tanh();
+        }
+        state.line.push(input + repeat);
+
+        input + self.mix * (delayed - input)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "time" => self.time = value,
+            "division" => self.division = value.round() as usize,
+            "tempo" => self.tempo = value,
+            "feedback" => self.feedback = value,
+            "mix" => self.mix = value,
+            "high_cut" => self.high_cut = value,
+            "mod_rate" => {
+                self.modulation.set_parameter("rate", value);
+            }
+            "mod_depth" => self.mod_depth = value,
+            "analog" => self.analog = value >= 0.5,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+        self.update_coefficients();
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("time".to_string(), self.time);
+        processor_details.insert("division".to_string(), self.division as f32);
+        processor_details.insert("tempo".to_string(), self.tempo);
+        processor_details.insert("feedback".to_string(), self.feedback);
+        processor_details.insert("mix".to_string(), self.mix);
+        processor_details.insert("high_cut".to_string(), self.high_cut);
+        processor_details.insert("mod_rate".to_string(), self.modulation.rate);
+        processor_details.insert("mod_depth".to_string(), self.mod_depth);
+        processor_details.insert("analog".to_string(), f32::from(u8::from(self.analog)));
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Delay {
+    pub fn new() -> Self {
+        let mut delay = Delay {
+            time: 400.0,
+            division: 0,
+            tempo: 120.0,
+            feedback: 0.4,
+            mix: 0.35,
+            high_cut: 6000.0,
+            mod_depth: 0.0,
+            analog: false,
+            modulation: Lfo::new(0.5),
+            sample_rate: 44_100.0,
+            target_samples: 0.0,
+            time_smoothing: 0.0,
+            high_cut_alpha: 0.0,
+            mod_depth_samples: 0.0,
+            // Sized in `prepare`, once the sample rate is known.
+            channels: std::array::from_fn(|_| DelayState::new(0)),
+        };
+        delay.update_coefficients();
+        delay
+    }
+
+    /// The delay time in milliseconds, from the tempo when synced.
+    fn time_ms(&self) -> f32 {
+        note_length_ms(self.division, self.tempo).map_or(self.time, |ms| ms.min(MAX_DELAY_MS))
+    }
+
+    fn update_coefficients(&mut self) {
+        let samples_per_ms = 0.001 * self.sample_rate;
+
+        self.target_samples = self.time_ms() * samples_per_ms;
+        self.time_smoothing = 1.0 - (-1.0 / (TIME_SMOOTHING_MS * samples_per_ms)).exp();
+
+        // One-pole low-pass, as in the amplifier's tone control.
+        let dt = 1.0 / self.sample_rate;
+        let rc = 1.0 / (TAU * self.high_cut);
+        self.high_cut_alpha = dt / (rc + dt);
+
+        self.mod_depth_samples = self.mod_depth * samples_per_ms;
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn an_impulse_repeats_after_the_delay_time() {
+        let mut delay = Delay::new();
+        delay.set_parameter("time", 10.0).unwrap();
+        delay.set_parameter("mix", 1.0).unwrap();
+        delay.set_parameter("high_cut", 20000.0).unwrap();
+        delay.prepare(48_000.0, 1, 64);
+
+        let output: Vec<f32> = (0..1000)
+            .map(|n| delay.process(0, if n == 0 { 1.0 } else { 0.0 }))
+            .collect();
+
+        let loudest = (0..output.len())
+            .max_by(|a, b| output[*a].abs().total_cmp(&output[*b].abs()))
+            .unwrap();
+        assert_eq!(loudest, 480);
+    }
+}
@@ -1,2 +1,12 @@
 pub mod amplifier;
+pub mod cabinet;
+pub mod chorus;
+pub mod compressor;
+pub mod delay;
+pub mod flanger;
+pub mod noise_gate;
+pub mod phaser;
+pub mod reverb;
 pub mod screamer;
+pub mod tremolo;
+pub mod vibrato;
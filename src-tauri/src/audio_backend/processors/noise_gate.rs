@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    dsp::{db_to_gain, time_coefficient},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+};
+
+pub const ID: &str = "noise_gate";
+
+pub const PARAMETERS: [ParameterDescriptor; 5] = [
+    ParameterDescriptor {
+        name: "threshold",
+        label: "Threshold",
+        min: -96.0,
+        max: 0.0,
+        default: -60.0,
+        unit: "dB",
+        scale: ParameterScale::Decibel,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "hysteresis",
+        label: "Hysteresis",
+        min: 0.0,
+        max: 24.0,
+        default: 6.0,
+        unit: "dB",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "attack",
+        label: "Attack",
+        min: 0.1,
+        max: 50.0,
+        default: 1.0,
+        unit: "ms",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "hold",
+        label: "Hold",
+        min: 0.0,
+        max: 500.0,
+        default: 50.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "release",
+        label: "Release",
+        min: 1.0,
+        max: 2000.0,
+        default: 100.0,
+        unit: "ms",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+];
+
+// How quickly the level detector falls back after a peak.
+const DETECTOR_RELEASE_MS: f32 = 10.0;
+
+/// Mutes the signal while it stays below the threshold, so hum and hiss don't get amplified
+/// between notes. The gate opens at `threshold` and only closes again once the level has fallen
+/// `hysteresis` below it and `hold` has passed, so it doesn't chatter on decaying notes.
+#[derive(Clone)]
+pub struct NoiseGate {
+    pub threshold: f32,
+    pub hysteresis: f32,
+    pub attack: f32,
+    pub hold: f32,
+    pub release: f32,
+    sample_rate: f32,
+    // Derived from the parameters and the sample rate.
+    open_level: f32,
+    close_level: f32,
+    attack_coefficient: f32,
+    release_coefficient: f32,
+    detector_coefficient: f32,
+    hold_samples: u32,
+    channels: [GateState; MAX_CHANNELS],
+}
+
+#[derive(Clone, Copy)]
+struct GateState {
+    envelope: f32,
+    gain: f32,
+    open: bool,
+    hold_remaining: u32,
+}
+
+impl GateState {
+    fn new() -> Self {
+        GateState {
+            envelope: 0.0,
+            gain: 0.0,
+            open: false,
+            hold_remaining: 0,
+        }
+    }
+}
+
+impl Processor for NoiseGate {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.channels = [GateState::new(); MAX_CHANNELS];
+        self.update_coefficients();
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let state = &mut self.channels[channel];
+
+        state.envelope = input.abs().max(state.envelope * self.detector_coefficient);
+
+        if state.envelope >= self.open_level {
+            state.open = true;
+            state.hold_remaining = self.hold_samples;
+        } else if state.open && state.envelope >= self.close_level {
+            state.hold_remaining = self.hold_samples;
+        } else if state.hold_remaining > 0 {
+            state.hold_remaining -= 1;
+        } else {
+            state.open = false;
+        }
+
+        let (target, coefficient) = if state.open {
+            (1.0, self.attack_coefficient)
+        } else {
+            (0.0, self.release_coefficient)
+        };
+        state.gain = target + coefficient * (state.gain - target);
+
+        input * state.gain
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "threshold" => self.threshold = value,
+            "hysteresis" => self.hysteresis = value,
+            "attack" => self.attack = value,
+            "hold" => self.hold = value,
+            "release" => self.release = value,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+        self.update_coefficients();
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("threshold".to_string(), self.threshold);
+        processor_details.insert("hysteresis".to_string(), self.hysteresis);
+        processor_details.insert("attack".to_string(), self.attack);
+        processor_details.insert("hold".to_string(), self.hold);
+        processor_details.insert("release".to_string(), self.release);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl NoiseGate {
+    pub fn new() -> Self {
+        let mut gate = NoiseGate {
+            threshold: -60.0,
+            hysteresis: 6.0,
+            attack: 1.0,
+            hold: 50.0,
+            release: 100.0,
+            sample_rate: 44_100.0,
+            open_level: 0.0,
+            close_level: 0.0,
+            attack_coefficient: 0.0,
+            release_coefficient: 0.0,
+            detector_coefficient: 0.0,
+            hold_samples: 0,
+            channels: [GateState::new(); MAX_CHANNELS],
+        };
+        gate.update_coefficients();
+        gate
+    }
+
+    fn update_coefficients(&mut self) {
+        self.open_level = db_to_gain(self.threshold);
+        self.close_level = db_to_gain(self.threshold - self.hysteresis);
+        self.attack_coefficient = time_coefficient(self.attack, self.sample_rate);
+        self.release_coefficient = time_coefficient(self.release, self.sample_rate);
+        self.detector_coefficient = time_coefficient(DETECTOR_RELEASE_MS, self.sample_rate);
+        self.hold_samples = (self.hold * 0.001 * self.sample_rate) as u32;
+    }
+}
+
+impl Default for NoiseGate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // At 1 kHz a sample is a millisecond, which keeps the hold easy to count.
+    fn gate(hysteresis: f32, hold: f32) -> NoiseGate {
+        let mut gate = NoiseGate::new();
+        gate.prepare(1_000.0, 1, 64);
+        gate.set_parameter("threshold", -20.0).unwrap();
+        gate.set_parameter("hysteresis", hysteresis).unwrap();
+        gate.set_parameter("hold", hold).unwrap();
+        gate
+    }
+
+    fn feed(gate: &mut NoiseGate, level: f32, samples: usize) {
+        for _ in 0..samples {
+            gate.process(0, level);
+        }
+    }
+
+    #[test]
+    fn opens_above_the_threshold_and_closes_below_it() {
+        let mut gate = gate(0.0, 0.0);
+        feed(&mut gate, 0.05, 100);
+        assert!(!gate.channels[0].open);
+
+        feed(&mut gate, 0.2, 1);
+        assert!(gate.channels[0].open);
+
+        feed(&mut gate, 0.0, 100);
+        assert!(!gate.channels[0].open);
+    }
+
+    // The threshold is 0.1 and the gate closes 6 dB below it, at about 0.05.
+    #[test]
+    fn stays_open_within_the_hysteresis() {
+        let mut gate = gate(6.0, 0.0);
+        feed(&mut gate, 0.07, 100);
+        assert!(!gate.channels[0].open);
+
+        feed(&mut gate, 0.2, 1);
+        feed(&mut gate, 0.07, 100);
+        assert!(gate.channels[0].open);
+
+        feed(&mut gate, 0.03, 100);
+        assert!(!gate.channels[0].open);
+    }
+
+    #[test]
+    fn holds_open_after_the_level_falls() {
+        let mut gate = gate(0.0, 50.0);
+        feed(&mut gate, 0.2, 1);
+
+        // The detector takes a few milliseconds to fall below the threshold before the hold
+        // starts.
+        feed(&mut gate, 0.0, 45);
+        assert!(gate.channels[0].open);
+
+        feed(&mut gate, 0.0, 25);
+        assert!(!gate.channels[0].open);
+    }
+}
@@ -0,0 +1,242 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    lfo::{Lfo, WAVEFORM_PARAMETER},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+    tap_tempo::{DIVISION_PARAMETER, TEMPO_PARAMETER},
+};
+
+const MAX_STAGES: usize = 12;
+
+// At full depth the sweep covers this many octaves either side of `center`.
+const SWEEP_OCTAVES: f32 = 2.0;
+
+pub const ID: &str = "phaser";
+
+pub const PARAMETERS: [ParameterDescriptor; 9] = [
+    ParameterDescriptor {
+        name: "stages",
+        label: "Stages",
+        min: 2.0,
+        max: MAX_STAGES as f32,
+        default: 4.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Stepped,
+    },
+    ParameterDescriptor {
+        name: "center",
+        label: "Center",
+        min: 200.0,
+        max: 4000.0,
+        default: 800.0,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "depth",
+        label: "Depth",
+        min: 0.0,
+        max: 1.0,
+        default: 0.7,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "feedback",
+        label: "Feedback",
+        min: 0.0,
+        max: 0.9,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mix",
+        label: "Mix",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "rate",
+        label: "Rate",
+        min: 0.05,
+        max: 5.0,
+        default: 0.5,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    WAVEFORM_PARAMETER,
+    DIVISION_PARAMETER,
+    TEMPO_PARAMETER,
+];
+
+/// A chain of first-order allpass filters whose break frequency the LFO sweeps around
+/// `center`. Mixed with the dry signal, every two stages cut one moving notch.
+#[derive(Clone)]
+pub struct Phaser {
+    pub stages: usize,
+    pub center: f32,
+    pub depth: f32,
+    pub feedback: f32,
+    pub mix: f32,
+    lfo: Lfo,
+    sample_rate: f32,
+    channels: [PhaserChannel; MAX_CHANNELS],
+}
+
+#[derive(Clone, Copy, Default)]
+struct PhaserChannel {
+    stages: [AllpassStage; MAX_STAGES],
+    // The last output, fed back into the first stage.
+    last: f32,
+}
+
+#[derive(Clone, Copy, Default)]
+struct AllpassStage {
+    input: f32,
+    output: f32,
+}
+
+impl Processor for Phaser {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.lfo.prepare(sample_rate, 0.0);
+        self.channels = [PhaserChannel::default(); MAX_CHANNELS];
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let lfo = self.lfo.next(channel);
+        let frequency = (self.center * 2f32.powf(SWEEP_OCTAVES * self.depth * lfo))
+            .min(0.45 * self.sample_rate);
+        let t = (PI * frequency / self.sample_rate).tan();
+        let coefficient = (t - 1.0) / (t + 1.0);
+
+        let state = &mut self.channels[channel];
+        let mut signal = input + state.last * self.feedback;
+        for stage in &mut state.stages[..self.stages] {
+            let output = coefficient * signal + stage.input - coefficient * stage.output;
+            stage.input = signal;
+            stage.output = output;
+            signal = output;
+        }
+        state.last = signal;
+
+        input + self.mix * (signal - input)
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "stages" => self.stages = value.round() as usize,
+            "center" => self.center = value,
+            "depth" => self.depth = value,
+            "feedback" => self.feedback = value,
+            "mix" => self.mix = value,
+            name if self.lfo.set_parameter(name, value) => {}
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("stages".to_string(), self.stages as f32);
+        processor_details.insert("center".to_string(), self.center);
+        processor_details.insert("depth".to_string(), self.depth);
+        processor_details.insert("feedback".to_string(), self.feedback);
+        processor_details.insert("mix".to_string(), self.mix);
+        self.lfo.insert_details(&mut processor_details);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Phaser {
+    pub fn new() -> Self {
+        Phaser {
+            stages: 4,
+            center: 800.0,
+            depth: 0.7,
+            feedback: 0.5,
+            mix: 0.5,
+            lfo: Lfo::new(0.5),
+            sample_rate: 44_100.0,
+            channels: [PhaserChannel::default(); MAX_CHANNELS],
+        }
+    }
+}
+
+impl Default for Phaser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn without_feedback_the_allpasses_keep_the_level() {
+        let mut phaser = Phaser::new();
+        phaser.set_parameter("depth", 0.0).unwrap();
+        phaser.set_parameter("feedback", 0.0).unwrap();
+        phaser.set_parameter("mix", 1.0).unwrap();
+        phaser.prepare(48_000.0, 1, 64);
+
+        let input = |n: usize| (2.0 * PI * 1000.0 * n as f32 / 48_000.0).sin();
+        let output: Vec<f32> = (0..9600).map(|n| phaser.process(0, input(n))).collect();
+
+        // Past the first few cycles, a 1 kHz sine comes out shifted but just as loud.
+        let steady = &output[4800..];
+        let peak = steady.iter().fold(0.0f32, |peak, sample| peak.max(sample.abs()));
+        assert!((peak - 1.0).abs() < 1e-3, "{}", peak);
+        assert!(steady
+            .iter()
+            .enumerate()
+            .any(|(n, sample)| (sample - input(n + 4800)).abs() > 0.1));
+    }
+}
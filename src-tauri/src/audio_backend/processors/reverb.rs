@@ -0,0 +1,316 @@
+use std::collections::HashMap;
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+};
+
+const MAX_PRE_DELAY_MS: f32 = 200.0;
+
+pub const ID: &str = "reverb";
+
+pub const PARAMETERS: [ParameterDescriptor; 4] = [
+    ParameterDescriptor {
+        name: "room_size",
+        label: "Room Size",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "damping",
+        label: "Damping",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "pre_delay",
+        label: "Pre-delay",
+        min: 0.0,
+        max: MAX_PRE_DELAY_MS,
+        default: 10.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "mix",
+        label: "Mix",
+        min: 0.0,
+        max: 1.0,
+        default: 0.25,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+];
+
+// Freeverb's tunings, in samples at 44.1 kHz. They are scaled to the stream rate in `prepare`.
+const COMB_TUNINGS: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNINGS: [usize; 4] = [556, 441, 341, 225];
+// Added to every delay on the right channel, which is what makes the tail stereo.
+const STEREO_SPREAD: usize = 23;
+const TUNING_RATE: f32 = 44_100.0;
+
+const INPUT_GAIN: f32 = 0.015;
+const WET_GAIN: f32 = 3.0;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// A Freeverb-style reverb: eight damped comb filters in parallel into four allpasses in
+/// series, per channel. It asks for a stereo buffer, and the right channel's delays are
+/// slightly longer, so even a mono guitar comes out with a wide tail.
+#[derive(Clone)]
+pub struct Reverb {
+    pub room_size: f32,
+    pub damping: f32,
+    pub pre_delay: f32,
+    pub mix: f32,
+    sample_rate: f32,
+    channels: [ReverbChannel; MAX_CHANNELS],
+}
+
+#[derive(Clone)]
+struct ReverbChannel {
+    pre_delay: Vec<f32>,
+    pre_delay_position: usize,
+    combs: [Comb; COMB_TUNINGS.len()],
+    allpasses: [Allpass; ALLPASS_TUNINGS.len()],
+}
+
+impl ReverbChannel {
+    fn new(sample_rate: f32, spread: usize) -> Self {
+        let scale = |tuning: usize| ((tuning + spread) as f32 * sample_rate / TUNING_RATE) as usize;
+
+        ReverbChannel {
+            pre_delay: vec![0.0; (MAX_PRE_DELAY_MS * 0.001 * sample_rate) as usize + 1],
+            pre_delay_position: 0,
+            combs: std::array::from_fn(|index| Comb::new(scale(COMB_TUNINGS[index]))),
+            allpasses: std::array::from_fn(|index| Allpass::new(scale(ALLPASS_TUNINGS[index]))),
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Comb {
+    buffer: Vec<f32>,
+    position: usize,
+    filter_state: f32,
+}
+
+impl Comb {
+    fn new(length: usize) -> Self {
+        Comb {
+            buffer: vec![0.0; length.max(1)],
+            position: 0,
+            filter_state: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.position];
+        self.filter_state = output * (1.0 - damping) + self.filter_state * damping;
+        self.buffer[self.position] = input + self.filter_state * feedback;
+        self.position = (self.position + 1) % self.buffer.len();
+        output
+    }
+}
+
+#[derive(Clone)]
+struct Allpass {
+    buffer: Vec<f32>,
+    position: usize,
+}
+
+impl Allpass {
+    fn new(length: usize) -> Self {
+        Allpass {
+            buffer: vec![0.0; length.max(1)],
+            position: 0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.position];
+        self.buffer[self.position] = input + delayed * ALLPASS_FEEDBACK;
+        self.position = (self.position + 1) % self.buffer.len();
+        delayed - input
+    }
+}
+
+impl Processor for Reverb {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.channels =
+            std::array::from_fn(|channel| ReverbChannel::new(sample_rate, channel * STEREO_SPREAD));
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let feedback = self.room_size * 0.28 + 0.7;
+        let damping = self.damping * 0.4;
+
+        let state = &mut self.channels[channel];
+
+        let length = state.pre_delay.len();
+        let pre_delay_samples =
+            ((self.pre_delay * 0.001 * self.sample_rate) as usize).min(length - 1);
+        state.pre_delay[state.pre_delay_position] = input;
+        let delayed =
+            state.pre_delay[(state.pre_delay_position + length - pre_delay_samples) % length];
+        state.pre_delay_position = (state.pre_delay_position + 1) % length;
+
+        let feed = delayed * INPUT_GAIN;
+        let mut wet = state
+            .combs
+            .iter_mut()
+            .map(|comb| comb.process(feed, feedback, damping))
+            .sum::<f32>();
+        for allpass in &mut state.allpasses {
+            wet = allpass.process(wet);
+        }
+
+        input * (1.0 - self.mix) + wet * WET_GAIN * self.mix
+    }
+
+    fn output_channels(&self) -> usize {
+        2
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "room_size" => self.room_size = value,
+            "damping" => self.damping = value,
+            "pre_delay" => self.pre_delay = value,
+            "mix" => self.mix = value,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("room_size".to_string(), self.room_size);
+        processor_details.insert("damping".to_string(), self.damping);
+        processor_details.insert("pre_delay".to_string(), self.pre_delay);
+        processor_details.insert("mix".to_string(), self.mix);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Reverb {
+    pub fn new() -> Self {
+        Reverb {
+            room_size: 0.5,
+            damping: 0.5,
+            pre_delay: 10.0,
+            mix: 0.25,
+            sample_rate: TUNING_RATE,
+            // Sized in `prepare`, once the sample rate is known.
+            channels: std::array::from_fn(|_| ReverbChannel::new(0.0, 0)),
+        }
+    }
+}
+
+impl Default for Reverb {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 44_100.0;
+
+    // Two seconds of the wet signal after an impulse, on both channels.
+    fn impulse_response(pre_delay: f32) -> [Vec<f32>; 2] {
+        let mut reverb = Reverb::new();
+        reverb.set_parameter("pre_delay", pre_delay).unwrap();
+        reverb.set_parameter("mix", 1.0).unwrap();
+        reverb.prepare(SAMPLE_RATE, 2, 64);
+
+        std::array::from_fn(|channel| {
+            (0..2 * SAMPLE_RATE as usize)
+                .map(|n| reverb.process(channel, if n == 0 { 1.0 } else { 0.0 }))
+                .collect()
+        })
+    }
+
+    fn onset(output: &[f32]) -> usize {
+        output.iter().position(|sample| *sample != 0.0).unwrap()
+    }
+
+    fn energy(output: &[f32]) -> f32 {
+        output.iter().map(|sample| sample * sample).sum()
+    }
+
+    #[test]
+    fn the_tail_decays() {
+        let [left, _] = impulse_response(0.0);
+        let window = SAMPLE_RATE as usize / 4;
+
+        let early = energy(&left[..window]);
+        let late = energy(&left[left.len() - window..]);
+        assert!(late < early * 0.01, "{} vs {}", late, early);
+    }
+
+    #[test]
+    fn pre_delay_shifts_the_start_of_the_tail() {
+        let [dry, _] = impulse_response(0.0);
+        let [delayed, _] = impulse_response(50.0);
+
+        // The tail starts once the impulse has been round the shortest comb.
+        assert_eq!(onset(&dry), COMB_TUNINGS[0]);
+        assert_eq!(onset(&delayed), COMB_TUNINGS[0] + (0.05 * SAMPLE_RATE) as usize);
+    }
+
+    #[test]
+    fn the_channels_are_spread_apart() {
+        let [left, right] = impulse_response(0.0);
+
+        assert_eq!(onset(&right), onset(&left) + STEREO_SPREAD);
+        assert_ne!(left, right);
+    }
+}
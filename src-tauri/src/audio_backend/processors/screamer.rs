@@ -1,7 +1,46 @@
 use std::collections::HashMap;
 
-use crate::audio_backend::processor_trait::{Processor, ProcessorHashMapValue};
-
+use crate::audio_backend::processor_trait::{
+    ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+    ProcessorHashMapValue,
+};
+
+pub const ID: &str = "screamer";
+
+pub const PARAMETERS: [ParameterDescriptor; 3] = [
+    ParameterDescriptor {
+        name: "overdrive",
+        label: "Overdrive",
+        min: 0.0,
+        max: 50.0,
+        default: 1.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "tone",
+        label: "Tone",
+        min: 0.0,
+        max: 1.0,
+        default: 1.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "level",
+        label: "Level",
+        min: 0.0,
+        max: 1.0,
+        default: 1.0,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+];
+
+#[derive(Clone)]
 pub struct ScreamerPedal {
     pub overdrive: f32, // Controls the amount of overdrive
     pub tone: f32,      // Tone control, typically affects mid frequencies
@@ -9,7 +48,7 @@ pub struct ScreamerPedal {
 }
 
 impl Processor for ScreamerPedal {
-    fn process(&self, input: f32) -> f32 {
+    fn process(&mut self, _channel: usize, input: f32) -> f32 {
         // Processing logic to simulate the Screamer pedal's effect:
         // 1. Soft clipping to produce overdrive
         // 2. Tone control to shape the sound
@@ -19,29 +58,30 @@ impl Processor for ScreamerPedal {
         let shaped_tone = self.apply_tone(clipped);
         shaped_tone * self.level
     }
-    fn update_values(&mut self, hash_map_values: HashMap<String, String>) {
-        self.overdrive = hash_map_values
-            .get("overdrive")
-            .unwrap()
-            .parse::<f32>()
-            .unwrap();
-
-        println!("Set overdrive to: {:#?}", self.overdrive);
-
-        self.tone = hash_map_values.get("tone").unwrap().parse::<f32>().unwrap();
-        println!("Set tone to: {:#?}", self.tone);
-
-        self.level = hash_map_values
-            .get("level")
-            .unwrap()
-            .parse::<f32>()
-            .unwrap();
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "overdrive" => self.overdrive = value,
+            "tone" => self.tone = value,
+            "level" => self.level = value,
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
 
-        println!("Set level to: {:#?}", self.level);
+        Ok(())
     }
 
     fn get_name(&self) -> &'static str {
-        "Screamer"
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
     }
 
     fn to_hash_map(&self) -> std::collections::HashMap<String, ProcessorHashMapValue> {
@@ -63,6 +103,10 @@ impl Processor for ScreamerPedal {
 
         processor_hash_map
     }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
 }
 
 impl ScreamerPedal {
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use crate::audio_backend::{
+    lfo::{Lfo, WAVEFORM_PARAMETER},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+    tap_tempo::{DIVISION_PARAMETER, TEMPO_PARAMETER},
+};
+
+pub const ID: &str = "tremolo";
+
+pub const PARAMETERS: [ParameterDescriptor; 5] = [
+    ParameterDescriptor {
+        name: "depth",
+        label: "Depth",
+        min: 0.0,
+        max: 1.0,
+        default: 0.5,
+        unit: "",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "rate",
+        label: "Rate",
+        min: 0.1,
+        max: 20.0,
+        default: 5.0,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    WAVEFORM_PARAMETER,
+    DIVISION_PARAMETER,
+    TEMPO_PARAMETER,
+];
+
+/// Swells the volume up and down with the LFO. At full depth the troughs are silent; a square
+/// wave chops the signal.
+#[derive(Clone)]
+pub struct Tremolo {
+    pub depth: f32,
+    lfo: Lfo,
+}
+
+impl Processor for Tremolo {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.lfo.prepare(sample_rate, 0.0);
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let lfo = self.lfo.next(channel);
+        input * (1.0 - self.depth * (0.5 - 0.5 * lfo))
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "depth" => self.depth = value,
+            name if self.lfo.set_parameter(name, value) => {}
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("depth".to_string(), self.depth);
+        self.lfo.insert_details(&mut processor_details);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Tremolo {
+    pub fn new() -> Self {
+        Tremolo {
+            depth: 0.5,
+            lfo: Lfo::new(5.0),
+        }
+    }
+}
+
+impl Default for Tremolo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The lowest and highest gain over one cycle of the LFO.
+    fn gain_range(depth: f32) -> (f32, f32) {
+        let mut tremolo = Tremolo::new();
+        tremolo.set_parameter("depth", depth).unwrap();
+        tremolo.set_parameter("rate", 1.0).unwrap();
+        tremolo.prepare(1000.0, 1, 64);
+
+        (0..1000)
+            .map(|_| tremolo.process(0, 1.0))
+            .fold((f32::MAX, f32::MIN), |(low, high), gain| {
+                (low.min(gain), high.max(gain))
+            })
+    }
+
+    #[test]
+    fn the_gain_dips_by_the_depth() {
+        for depth in [0.0, 0.3, 1.0] {
+            let (low, high) = gain_range(depth);
+            assert!((low - (1.0 - depth)).abs() < 1e-4, "{} at {}", low, depth);
+            assert!((high - 1.0).abs() < 1e-4, "{} at {}", high, depth);
+        }
+    }
+}
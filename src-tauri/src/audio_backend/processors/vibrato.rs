@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+
+use crate::audio_backend::{
+    audio_buffer::MAX_CHANNELS,
+    delay_line::DelayLine,
+    lfo::{Lfo, WAVEFORM_PARAMETER},
+    processor_trait::{
+        ParameterDescriptor, ParameterError, ParameterKind, ParameterScale, Processor,
+        ProcessorHashMapValue,
+    },
+    tap_tempo::{DIVISION_PARAMETER, TEMPO_PARAMETER},
+};
+
+const MAX_DEPTH_MS: f32 = 5.0;
+
+pub const ID: &str = "vibrato";
+
+pub const PARAMETERS: [ParameterDescriptor; 5] = [
+    ParameterDescriptor {
+        name: "depth",
+        label: "Depth",
+        min: 0.0,
+        max: MAX_DEPTH_MS,
+        default: 1.0,
+        unit: "ms",
+        scale: ParameterScale::Linear,
+        kind: ParameterKind::Continuous,
+    },
+    ParameterDescriptor {
+        name: "rate",
+        label: "Rate",
+        min: 0.1,
+        max: 14.0,
+        default: 5.0,
+        unit: "Hz",
+        scale: ParameterScale::Log,
+        kind: ParameterKind::Continuous,
+    },
+    WAVEFORM_PARAMETER,
+    DIVISION_PARAMETER,
+    TEMPO_PARAMETER,
+];
+
+/// Bends the pitch up and down by sweeping a short delay with the LFO. There is no dry signal;
+/// mixing one back in is what makes a chorus.
+#[derive(Clone)]
+pub struct Vibrato {
+    pub depth: f32,
+    lfo: Lfo,
+    sample_rate: f32,
+    channels: [DelayLine; MAX_CHANNELS],
+}
+
+impl Processor for Vibrato {
+    fn prepare(&mut self, sample_rate: f32, _channels: usize, _max_block_size: usize) {
+        self.sample_rate = sample_rate;
+        self.lfo.prepare(sample_rate, 0.0);
+
+        let length = (2.0 * MAX_DEPTH_MS * 0.001 * sample_rate) as usize + 1;
+        self.channels = std::array::from_fn(|_| DelayLine::new(length));
+    }
+
+    fn process(&mut self, channel: usize, input: f32) -> f32 {
+        let lfo = self.lfo.next(channel);
+        let depth_samples = self.depth * 0.001 * self.sample_rate;
+
+        let line = &mut self.channels[channel];
+        line.push(input);
+        line.read(1.0 + depth_samples * (1.0 + lfo))
+    }
+
+    fn set_parameter(&mut self, name: &str, value: f32) -> Result<(), ParameterError> {
+        let descriptor = self.validate_parameter(name, value)?;
+
+        match descriptor.name {
+            "depth" => self.depth = value,
+            name if self.lfo.set_parameter(name, value) => {}
+            _ => {
+                return Err(ParameterError::UnknownParameter {
+                    processor: self.get_name().to_string(),
+                    name: name.to_string(),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get_name(&self) -> &'static str {
+        ID
+    }
+
+    fn parameters(&self) -> &'static [ParameterDescriptor] {
+        &PARAMETERS
+    }
+
+    fn to_hash_map(&self) -> HashMap<String, ProcessorHashMapValue> {
+        let mut processor_hash_map = HashMap::new();
+        let mut processor_details = HashMap::new();
+
+        processor_details.insert("depth".to_string(), self.depth);
+        self.lfo.insert_details(&mut processor_details);
+
+        processor_hash_map.insert(
+            "name".to_string(),
+            ProcessorHashMapValue::Str(self.get_name().to_string()),
+        );
+        processor_hash_map.insert(
+            "details".to_string(),
+            ProcessorHashMapValue::Map(processor_details),
+        );
+
+        processor_hash_map
+    }
+
+    fn clone_box(&self) -> Box<dyn Processor> {
+        Box::new(self.clone())
+    }
+}
+
+impl Vibrato {
+    pub fn new() -> Self {
+        Vibrato {
+            depth: 1.0,
+            lfo: Lfo::new(5.0),
+            sample_rate: 44_100.0,
+            // Sized in `prepare`, once the sample rate is known.
+            channels: std::array::from_fn(|_| DelayLine::new(0)),
+        }
+    }
+}
+
+impl Default for Vibrato {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,122 @@
+/// Converts a mono stream from the input device rate to the output device rate using
+/// linear interpolation. When both rates match it is a plain copy.
+pub struct Resampler {
+    ratio: f64, // Input rate over output rate
+    step: f64,  // Input frames advanced per output frame, including any drift
+    position: f64,
+    previous: f32,
+    current: f32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        let ratio = input_rate as f64 / output_rate as f64;
+        Resampler {
+            ratio,
+            step: ratio,
+            position: 0.0,
+            previous: 0.0,
+            current: 0.0,
+        }
+    }
+
+    /// Reads input faster than its rate by `drift`, a fraction such as 0.002. A small drift
+    /// drains a backlog without the discontinuity of dropping samples.
+    pub fn set_drift(&mut self, drift: f64) {
+        self.step = self.ratio * (1.0 + drift);
+    }
+
+    /// Fills `output`, pulling input samples from `next_input` as they are needed.
+    /// Returns false if the input ran dry; missing samples are treated as silence.
+    pub fn process(
+        &mut self,
+        output: &mut [f32],
+        mut next_input: impl FnMut() -> Option<f32>,
+    ) -> bool {
+        let mut complete = true;
+
+        for sample in output {
+            while self.position >= 1.0 {
+                self.previous = self.current;
+                self.current = next_input().unwrap_or_else(|| {
+                    complete = false;
+                    0.0
+                });
+                self.position -= 1.0;
+            }
+
+            let fraction = self.position as f32;
+            *sample = self.previous + (self.current - self.previous) * fraction;
+            self.position += self.step;
+        }
+
+        complete
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Counts how many input samples it takes to fill `output_frames` frames.
+    fn input_consumed(input_rate: u32, output_rate: u32, output_frames: usize) -> usize {
+        let mut resampler = Resampler::new(input_rate, output_rate);
+        let mut output = vec![0.0; output_frames];
+        let mut consumed = 0;
+        resampler.process(&mut output, || {
+            consumed += 1;
+            Some(0.0)
+        });
+        consumed
+    }
+
+    #[test]
+    fn pulls_input_at_the_rate_ratio() {
+        // A second of output takes a second of input, give or take a couple of samples, since
+        // input is only pulled once an output frame reaches it.
+        for (input_rate, output_rate) in [(44_100, 48_000), (48_000, 44_100), (96_000, 48_000)] {
+            let consumed = input_consumed(input_rate, output_rate, output_rate as usize);
+            assert!(
+                consumed.abs_diff(input_rate as usize) <= 2,
+                "{} -> {} pulled {}",
+                input_rate,
+                output_rate,
+                consumed
+            );
+        }
+    }
+
+    #[test]
+    fn matching_rates_copy_the_input() {
+        let mut resampler = Resampler::new(48_000, 48_000);
+        let mut input = (1..=8).map(|sample| sample as f32);
+        let mut output = [0.0; 8];
+        assert!(resampler.process(&mut output, || input.next()));
+        // Two samples behind, as interpolation starts between two samples of silence.
+        assert_eq!(output, [0.0, 0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn drift_reads_input_faster() {
+        let mut resampler = Resampler::new(48_000, 48_000);
+        resampler.set_drift(0.002);
+
+        let mut output = vec![0.0; 48_000];
+        let mut consumed: usize = 0;
+        resampler.process(&mut output, || {
+            consumed += 1;
+            Some(0.0)
+        });
+        assert!(consumed.abs_diff(48_096) <= 2, "pulled {}", consumed);
+
+        resampler.set_drift(0.0);
+        assert_eq!(resampler.step, 1.0);
+    }
+
+    #[test]
+    fn reports_running_dry() {
+        let mut resampler = Resampler::new(44_100, 48_000);
+        let mut output = [0.0; 16];
+        assert!(!resampler.process(&mut output, || None));
+    }
+}
@@ -0,0 +1,363 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::pipeline_controller::ProcessorId;
+
+// Each level of splits needs its own pair of buffers on the audio thread.
+pub const MAX_SPLIT_DEPTH: usize = 4;
+
+/// One step of the signal path. Nodes in a list run in series; a split feeds the same signal to
+/// every branch and sums what comes out of them.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RouteNode {
+    Processor { id: ProcessorId },
+    Split { branches: Vec<Branch> },
+}
+
+/// A series of nodes inside a split. An empty branch passes the dry signal, which together with
+/// a wet branch makes an effect loop.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub gain: f32,
+    pub nodes: Vec<RouteNode>,
+}
+
+/// The routing flattened into a program for the audio thread, so it runs without recursion.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RouteStep {
+    /// Runs the processor in this slot of the realtime pool.
+    Process(usize),
+    SplitBegin,
+    BranchBegin,
+    BranchEnd(f32),
+    SplitEnd,
+}
+
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RoutingError {
+    UnknownProcessor { id: ProcessorId },
+    DuplicateProcessor { id: ProcessorId },
+    MissingProcessor { id: ProcessorId },
+    TooDeep { max: usize },
+    EmptySplit,
+    InvalidGain { gain: f32 },
+}
+
+impl fmt::Display for RoutingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RoutingError::UnknownProcessor { id } => write!(f, "Unknown processor {}", id),
+            RoutingError::DuplicateProcessor { id } => {
+                write!(f, "Processor {} is routed more than once", id)
+            }
+            RoutingError::MissingProcessor { id } => write!(f, "Processor {} is not routed", id),
+            RoutingError::TooDeep { max } => write!(f, "Splits can only be nested {} deep", max),
+            RoutingError::EmptySplit => write!(f, "A split needs at least one branch"),
+            RoutingError::InvalidGain { gain } => write!(f, "Invalid branch gain {}", gain),
+        }
+    }
+}
+
+impl std::error::Error for RoutingError {}
+
+/// Checks that `nodes` routes every processor in `ids` exactly once and fits the audio thread.
+pub fn validate(nodes: &[RouteNode], ids: &[ProcessorId]) -> Result<(), RoutingError> {
+    let mut seen = HashSet::new();
+    validate_nodes(nodes, ids, &mut seen, 0)?;
+
+    match ids.iter().find(|id| !seen.contains(*id)) {
+        Some(id) => Err(RoutingError::MissingProcessor { id: *id }),
+        None => Ok(()),
+    }
+}
+
+fn validate_nodes(
+    nodes: &[RouteNode],
+    ids: &[ProcessorId],
+    seen: &mut HashSet<ProcessorId>,
+    depth: usize,
+) -> Result<(), RoutingError> {
+    for node in nodes {
+        match node {
+            RouteNode::Processor { id } => {
+                if !ids.contains(id) {
+                    return Err(RoutingError::UnknownProcessor { id: *id });
+                }
+                if !seen.insert(*id) {
+                    return Err(RoutingError::DuplicateProcessor { id: *id });
+                }
+            }
+            RouteNode::Split { branches } => {
+                if depth >= MAX_SPLIT_DEPTH {
+                    return Err(RoutingError::TooDeep {
+                        max: MAX_SPLIT_DEPTH,
+                    });
+                }
+                if branches.is_empty() {
+                    return Err(RoutingError::EmptySplit);
+                }
+                for branch in branches {
+                    if !branch.gain.is_finite() || branch.gain < 0.0 {
+                        return Err(RoutingError::InvalidGain { gain: branch.gain });
+                    }
+                    validate_nodes(&branch.nodes, ids, seen, depth + 1)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Flattens validated routing into steps. `pool_index` maps an ID to its realtime slot.
+pub fn compile(
+    nodes: &[RouteNode],
+    pool_index: &impl Fn(ProcessorId) -> Option<usize>,
+    steps: &mut Vec<RouteStep>,
+) {
+    for node in nodes {
+        match node {
+            RouteNode::Processor { id } => {
+                if let Some(index) = pool_index(*id) {
+                    steps.push(RouteStep::Process(index));
+                }
+            }
+            RouteNode::Split { branches } => {
+                steps.push(RouteStep::SplitBegin);
+                for branch in branches {
+                    steps.push(RouteStep::BranchBegin);
+                    compile(&branch.nodes, pool_index, steps);
+                    steps.push(RouteStep::BranchEnd(branch.gain));
+                }
+                steps.push(RouteStep::SplitEnd);
+            }
+        }
+    }
+}
+
+/// Takes a processor out of the routing, wherever it is.
+pub fn remove(nodes: &mut Vec<RouteNode>, id: ProcessorId) -> bool {
+    if let Some(index) = nodes
+        .iter()
+        .position(|node| *node == RouteNode::Processor { id })
+    {
+        nodes.remove(index);
+        return true;
+    }
+
+    nodes.iter_mut().any(|node| match node {
+        RouteNode::Split { branches } => branches
+            .iter_mut()
+            .any(|branch| remove(&mut branch.nodes, id)),
+        RouteNode::Processor { .. } => false,
+    })
+}
+
+/// Exchanges the places of two processors in the routing.
+pub fn swap(nodes: &mut [RouteNode], first: ProcessorId, second: ProcessorId) {
+    for node in nodes {
+        match node {
+            RouteNode::Processor { id } if *id == first => *id = second,
+            RouteNode::Processor { id } if *id == second => *id = first,
+            RouteNode::Processor { .. } => {}
+            RouteNode::Split { branches } => {
+                for branch in branches {
+                    swap(&mut branch.nodes, first, second);
+                }
+            }
+        }
+    }
+}
+
+/// Renames processors using `ids`, dropping any that aren't in it.
+pub fn remap(nodes: &mut Vec<RouteNode>, ids: &HashMap<ProcessorId, ProcessorId>) {
+    nodes.retain_mut(|node| match node {
+        RouteNode::Processor { id } => match ids.get(id) {
+            Some(new_id) => {
+                *id = *new_id;
+                true
+            }
+            None => false,
+        },
+        RouteNode::Split { branches } => {
+            for branch in branches {
+                remap(&mut branch.nodes, ids);
+            }
+            true
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn processor(id: ProcessorId) -> RouteNode {
+        RouteNode::Processor { id }
+    }
+
+    fn split(branches: Vec<Vec<RouteNode>>) -> RouteNode {
+        RouteNode::Split {
+            branches: branches
+                .into_iter()
+                .map(|nodes| Branch { gain: 0.5, nodes })
+                .collect(),
+        }
+    }
+
+    // 1 -> (2 -> 3 | dry) -> 4
+    fn effect_loop() -> Vec<RouteNode> {
+        vec![
+            processor(1),
+            split(vec![vec![processor(2), processor(3)], vec![]]),
+            processor(4),
+        ]
+    }
+
+    // `depth` splits, each nested in the branch of the one before, around processor 1.
+    fn nested(depth: usize) -> Vec<RouteNode> {
+        (0..depth).fold(vec![processor(1)], |nodes, _| vec![split(vec![nodes])])
+    }
+
+    #[test]
+    fn accepts_a_route_with_every_processor_once() {
+        assert!(validate(&effect_loop(), &[1, 2, 3, 4]).is_ok());
+    }
+
+    #[test]
+    fn rejects_unknown_duplicate_and_missing_processors() {
+        assert!(matches!(
+            validate(&effect_loop(), &[1, 2, 3]),
+            Err(RoutingError::UnknownProcessor { id: 4 })
+        ));
+        assert!(matches!(
+            validate(&[processor(1), processor(1)], &[1]),
+            Err(RoutingError::DuplicateProcessor { id: 1 })
+        ));
+        assert!(matches!(
+            validate(&effect_loop(), &[1, 2, 3, 4, 5]),
+            Err(RoutingError::MissingProcessor { id: 5 })
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_gains() {
+        for gain in [-1.0, f32::NAN, f32::INFINITY] {
+            let route = vec![RouteNode::Split {
+                branches: vec![Branch {
+                    gain,
+                    nodes: vec![processor(1)],
+                }],
+            }];
+            assert!(matches!(
+                validate(&route, &[1]),
+                Err(RoutingError::InvalidGain { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn rejects_a_split_without_branches() {
+        let route = vec![processor(1), RouteNode::Split { branches: vec![] }];
+        assert!(matches!(
+            validate(&route, &[1]),
+            Err(RoutingError::EmptySplit)
+        ));
+    }
+
+    #[test]
+    fn limits_split_depth() {
+        assert!(validate(&nested(MAX_SPLIT_DEPTH), &[1]).is_ok());
+        assert!(matches!(
+            validate(&nested(MAX_SPLIT_DEPTH + 1), &[1]),
+            Err(RoutingError::TooDeep {
+                max: MAX_SPLIT_DEPTH
+            })
+        ));
+    }
+
+    #[test]
+    fn compiles_to_steps() {
+        let pool_index = |id: ProcessorId| Some(id as usize * 10);
+        let mut steps = Vec::new();
+        compile(&effect_loop(), &pool_index, &mut steps);
+
+        assert_eq!(
+            steps,
+            [
+                RouteStep::Process(10),
+                RouteStep::SplitBegin,
+                RouteStep::BranchBegin,
+                RouteStep::Process(20),
+                RouteStep::Process(30),
+                RouteStep::BranchEnd(0.5),
+                RouteStep::BranchBegin,
+                RouteStep::BranchEnd(0.5),
+                RouteStep::SplitEnd,
+                RouteStep::Process(40),
+            ]
+        );
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let route = effect_loop();
+        let json = serde_json::to_string(&route).unwrap();
+
+        assert_eq!(
+            serde_json::from_str::<Vec<RouteNode>>(&json).unwrap(),
+            route
+        );
+    }
+
+    #[test]
+    fn remaps_and_back() {
+        let forward: HashMap<_, _> = [(1, 11), (2, 12), (3, 13), (4, 14)].into();
+        let back: HashMap<_, _> = forward.iter().map(|(from, to)| (*to, *from)).collect();
+
+        let mut route = effect_loop();
+        remap(&mut route, &forward);
+        assert!(validate(&route, &[11, 12, 13, 14]).is_ok());
+
+        remap(&mut route, &back);
+        assert_eq!(route, effect_loop());
+    }
+
+    #[test]
+    fn remap_drops_processors_it_does_not_know() {
+        let mut route = effect_loop();
+        remap(&mut route, &[(1, 1), (3, 3), (4, 4)].into());
+
+        assert_eq!(
+            route,
+            [
+                processor(1),
+                split(vec![vec![processor(3)], vec![]]),
+                processor(4)
+            ]
+        );
+    }
+
+    #[test]
+    fn removes_and_swaps_inside_splits() {
+        let mut route = effect_loop();
+        swap(&mut route, 1, 3);
+        assert!(remove(&mut route, 2));
+        assert!(!remove(&mut route, 2));
+
+        assert_eq!(
+            route,
+            [
+                processor(3),
+                split(vec![vec![processor(1)], vec![]]),
+                processor(4)
+            ]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+/// Measurements written by the stream callbacks and read by Tauri commands.
+/// Everything is atomic so the callbacks never wait on the UI.
+pub struct StreamTelemetry {
+    input_rate: AtomicU32,
+    output_rate: AtomicU32,
+    input_block_frames: AtomicU32,
+    output_block_frames: AtomicU32,
+    buffered_frames: AtomicU32, // Ring buffer fill, at the input rate
+    target_frames: AtomicU32,   // Pre-fill the output callback is aiming for
+    overruns: AtomicU64,        // Input callbacks that found the ring buffer full
+    underruns: AtomicU64,       // Output callbacks that found the ring buffer empty
+    callback_us: AtomicU32,
+    max_callback_us: AtomicU32,
+    cpu_load: AtomicU32, // f32 bits, callback time over the time the block represents
+    peak_cpu_load: AtomicU32, // f32 bits
+}
+
+#[derive(Serialize, Clone)]
+pub struct LatencyReport {
+    pub input_ms: f32,
+    pub buffer_ms: f32,
+    pub target_buffer_ms: f32,
+    pub output_ms: f32,
+    pub processing_ms: f32,
+    pub round_trip_ms: f32,
+}
+
+#[derive(Serialize, Clone)]
+pub struct StreamStats {
+    pub overruns: u64,
+    pub underruns: u64,
+    pub callback_us: u32,
+    pub max_callback_us: u32,
+    pub cpu_load: f32,
+    pub peak_cpu_load: f32,
+    pub latency: LatencyReport,
+}
+
+impl StreamTelemetry {
+    pub fn new() -> Self {
+        StreamTelemetry {
+            input_rate: AtomicU32::new(0),
+            output_rate: AtomicU32::new(0),
+            input_block_frames: AtomicU32::new(0),
+            output_block_frames: AtomicU32::new(0),
+            buffered_frames: AtomicU32::new(0),
+            target_frames: AtomicU32::new(0),
+            overruns: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
+            callback_us: AtomicU32::new(0),
+            max_callback_us: AtomicU32::new(0),
+            cpu_load: AtomicU32::new(0.0_f32.to_bits()),
+            peak_cpu_load: AtomicU32::new(0.0_f32.to_bits()),
+        }
+    }
+
+    pub fn reset(&self, input_rate: u32, output_rate: u32, target_frames: usize) {
+        self.input_rate.store(input_rate, Ordering::Relaxed);
+        self.output_rate.store(output_rate, Ordering::Relaxed);
+        self.input_block_frames.store(0, Ordering::Relaxed);
+        self.output_block_frames.store(0, Ordering::Relaxed);
+        self.buffered_frames
+            .store(target_frames as u32, Ordering::Relaxed);
+        self.target_frames
+            .store(target_frames as u32, Ordering::Relaxed);
+        self.overruns.store(0, Ordering::Relaxed);
+        self.underruns.store(0, Ordering::Relaxed);
+        self.callback_us.store(0, Ordering::Relaxed);
+        self.max_callback_us.store(0, Ordering::Relaxed);
+        self.cpu_load.store(0.0_f32.to_bits(), Ordering::Relaxed);
+        self.peak_cpu_load
+            .store(0.0_f32.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn record_overrun(&self) {
+        self.overruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_underrun(&self) {
+        self.underruns.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records how long an output callback took to render `frames` frames.
+    pub fn record_callback(&self, elapsed: Duration, frames: usize) {
+        let elapsed_us = elapsed.as_micros() as u32;
+        self.callback_us.store(elapsed_us, Ordering::Relaxed);
+        self.max_callback_us
+            .fetch_max(elapsed_us, Ordering::Relaxed);
+
+        let output_rate = self.output_rate.load(Ordering::Relaxed);
+        if frames == 0 || output_rate == 0 {
+            return;
+        }
+
+        let block_us = frames as f32 * 1_000_000.0 / output_rate as f32;
+        let load = elapsed_us as f32 / block_us;
+        self.cpu_load.store(load.to_bits(), Ordering::Relaxed);
+
+        // Non-negative floats order the same as their bit patterns.
+        self.peak_cpu_load
+            .fetch_max(load.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn record_input_block(&self, frames: usize) {
+        self.input_block_frames
+            .store(frames as u32, Ordering::Relaxed);
+    }
+
+    pub fn record_output_block(&self, frames: usize, buffered_frames: usize, target_frames: usize) {
+        self.output_block_frames
+            .store(frames as u32, Ordering::Relaxed);
+        self.buffered_frames
+            .store(buffered_frames as u32, Ordering::Relaxed);
+        self.target_frames
+            .store(target_frames as u32, Ordering::Relaxed);
+    }
+
+    /// Everything the callbacks measured, with `processing_ms` from the chain as in `latency`.
+    pub fn stats(&self, processing_ms: f32) -> StreamStats {
+        StreamStats {
+            overruns: self.overruns.load(Ordering::Relaxed),
+            underruns: self.underruns.load(Ordering::Relaxed),
+            callback_us: self.callback_us.load(Ordering::Relaxed),
+            max_callback_us: self.max_callback_us.load(Ordering::Relaxed),
+            cpu_load: f32::from_bits(self.cpu_load.load(Ordering::Relaxed)),
+            peak_cpu_load: f32::from_bits(self.peak_cpu_load.load(Ordering::Relaxed)),
+            latency: self.latency(processing_ms),
+        }
+    }
+
+    /// The latency of each stage of the stream, plus `processing_ms` from the chain itself.
+    pub fn latency(&self, processing_ms: f32) -> LatencyReport {
+        let to_ms = |frames: &AtomicU32, rate: &AtomicU32| {
+            let rate = rate.load(Ordering::Relaxed);
+            if rate == 0 {
+                0.0
+            } else {
+                frames.load(Ordering::Relaxed) as f32 * 1_000.0 / rate as f32
+            }
+        };
+
+        let input_ms = to_ms(&self.input_block_frames, &self.input_rate);
+        let buffer_ms = to_ms(&self.buffered_frames, &self.input_rate);
+        let target_buffer_ms = to_ms(&self.target_frames, &self.input_rate);
+        let output_ms = to_ms(&self.output_block_frames, &self.output_rate);
+
+        LatencyReport {
+            input_ms,
+            buffer_ms,
+            target_buffer_ms,
+            output_ms,
+            processing_ms,
+            round_trip_ms: input_ms + buffer_ms + output_ms + processing_ms,
+        }
+    }
+}
+
+impl Default for StreamTelemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_round_trip_includes_the_chain() {
+        let telemetry = StreamTelemetry::new();
+        telemetry.reset(48_000, 48_000, 480);
+        telemetry.record_input_block(96);
+        telemetry.record_output_block(96, 480, 480);
+
+        let report = telemetry.latency(1.5);
+        assert_eq!(report.input_ms, 2.0);
+        assert_eq!(report.buffer_ms, 10.0);
+        assert_eq!(report.output_ms, 2.0);
+        assert_eq!(report.round_trip_ms, 15.5);
+    }
+}
@@ -0,0 +1,193 @@
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::{
+    pipeline_controller::ProcessorId,
+    processor_trait::{ParameterDescriptor, ParameterKind, ParameterScale},
+};
+
+// Taps further apart than this start a new tempo.
+const TAP_TIMEOUT: Duration = Duration::from_secs(2);
+
+// The tempo is averaged over the last few taps, so one uneven tap doesn't throw it off.
+const MAX_TAPS: usize = 4;
+
+/// Note lengths in beats for a processor's `division` parameter: off, 1/1, 1/2, 1/4, 1/8,
+/// dotted 1/8, 1/8 triplet and 1/16.
+pub const DIVISIONS: [f32; 8] = [0.0, 4.0, 2.0, 1.0, 0.5, 0.75, 1.0 / 3.0, 0.25];
+
+/// The parameters of anything that syncs to a tempo, which also gives it a tap button.
+pub const DIVISION_PARAMETER: ParameterDescriptor = ParameterDescriptor {
+    name: "division",
+    label: "Sync",
+    min: 0.0,
+    max: (DIVISIONS.len() - 1) as f32,
+    default: 0.0,
+    unit: "",
+    scale: ParameterScale::Linear,
+    kind: ParameterKind::Choice {
+        options: &[
+            "Off",
+            "1/1",
+            "1/2",
+            "1/4",
+            "1/8",
+            "Dotted 1/8",
+            "1/8 Triplet",
+            "1/16",
+        ],
+    },
+};
+
+pub const TEMPO_PARAMETER: ParameterDescriptor = ParameterDescriptor {
+    name: "tempo",
+    label: "Tempo",
+    min: 40.0,
+    max: 300.0,
+    default: 120.0,
+    unit: "BPM",
+    scale: ParameterScale::Linear,
+    kind: ParameterKind::Continuous,
+};
+
+/// The parameter a tap was applied to, and its new value.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+pub struct TappedParameter {
+    pub name: &'static str,
+    pub value: f32,
+}
+
+/// What a tap sets on a processor whose `division` is off, where `tempo` isn't heard: a
+/// delay's `time` becomes one beat, and a modulation effect's `rate` one cycle per beat.
+pub fn unsynced_parameter(
+    parameters: &'static [ParameterDescriptor],
+    tempo: f32,
+) -> Option<TappedParameter> {
+    parameters.iter().find_map(|parameter| {
+        let value = match parameter.name {
+            "time" => 60_000.0 / tempo,
+            "rate" => tempo / 60.0,
+            _ => return None,
+        };
+
+        Some(TappedParameter {
+            name: parameter.name,
+            value: parameter.clamp(value),
+        })
+    })
+}
+
+/// The length of a `division` at `tempo` in milliseconds, or `None` when it is off.
+pub fn note_length_ms(division: usize, tempo: f32) -> Option<f32> {
+    match DIVISIONS.get(division) {
+        Some(beats) if *beats > 0.0 => Some(60_000.0 / tempo * beats),
+        _ => None,
+    }
+}
+
+/// Turns taps on a processor's tap button into a tempo.
+pub struct TapTempo {
+    id: Option<ProcessorId>,
+    taps: Vec<Instant>,
+}
+
+impl TapTempo {
+    pub fn new() -> Self {
+        TapTempo {
+            id: None,
+            taps: Vec::with_capacity(MAX_TAPS),
+        }
+    }
+
+    /// Records a tap and returns the tempo in BPM once there are at least two.
+    pub fn tap(&mut self, id: ProcessorId, now: Instant) -> Option<f32> {
+        let timed_out = !matches!(
+            self.taps.last(),
+            Some(last) if now.duration_since(*last) <= TAP_TIMEOUT
+        );
+        if self.id != Some(id) || timed_out {
+            self.id = Some(id);
+            self.taps.clear();
+        }
+
+        if self.taps.len() == MAX_TAPS {
+            self.taps.remove(0);
+        }
+        self.taps.push(now);
+
+        let first = self.taps.first()?;
+        let intervals = (self.taps.len() - 1) as f32;
+        if intervals == 0.0 {
+            return None;
+        }
+
+        let average = now.duration_since(*first).as_secs_f32() / intervals;
+        Some(60.0 / average)
+    }
+}
+
+impl Default for TapTempo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tap_at(tap_tempo: &mut TapTempo, id: ProcessorId, start: Instant, ms: u64) -> Option<f32> {
+        tap_tempo.tap(id, start + Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn averages_taps_into_bpm() {
+        let mut tap_tempo = TapTempo::new();
+        let start = Instant::now();
+
+        assert_eq!(tap_at(&mut tap_tempo, 1, start, 0), None);
+        let tempo = tap_at(&mut tap_tempo, 1, start, 500).unwrap();
+        assert!((tempo - 120.0).abs() < 0.01);
+
+        // 480 and 520 ms average out to 120 BPM again.
+        tap_at(&mut tap_tempo, 1, start, 980);
+        let tempo = tap_at(&mut tap_tempo, 1, start, 1500).unwrap();
+        assert!((tempo - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn only_averages_the_last_taps() {
+        let mut tap_tempo = TapTempo::new();
+        let start = Instant::now();
+
+        for beat in 0..4 {
+            tap_at(&mut tap_tempo, 1, start, beat * 1000);
+        }
+        // Enough 500 ms taps push the 1000 ms ones out.
+        let mut tempo = None;
+        for beat in 1..=MAX_TAPS as u64 {
+            tempo = tap_at(&mut tap_tempo, 1, start, 3000 + beat * 500);
+        }
+        assert!((tempo.unwrap() - 120.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn starts_over_after_a_pause_or_on_another_processor() {
+        let mut tap_tempo = TapTempo::new();
+        let start = Instant::now();
+
+        tap_at(&mut tap_tempo, 1, start, 0);
+        assert_eq!(tap_at(&mut tap_tempo, 1, start, 2500), None);
+        assert_eq!(tap_at(&mut tap_tempo, 2, start, 3000), None);
+        assert!(tap_at(&mut tap_tempo, 2, start, 3500).is_some());
+    }
+
+    #[test]
+    fn note_lengths_follow_the_tempo() {
+        assert_eq!(note_length_ms(0, 120.0), None);
+        assert_eq!(note_length_ms(3, 120.0), Some(500.0));
+        assert_eq!(note_length_ms(4, 120.0), Some(250.0));
+        assert_eq!(note_length_ms(1, 60.0), Some(4000.0));
+    }
+}
@@ -2,12 +2,37 @@ use std::path::Path;
 
 use serde::{Deserialize, Serialize};
 
+use crate::audio_backend::pipeline_controller::PipelineState;
+
 use super::config::Config;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AudioConfig {
     pub previous_input_device: Option<String>,
     pub previous_output_device: Option<String>,
+    #[serde(default)]
+    pub input_channel: usize, // Which input channel carries the guitar
+    #[serde(default)]
+    pub latency: LatencySettings,
+    #[serde(default)]
+    pub pipeline: Option<PipelineState>, // The chain from the last session
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LatencySettings {
+    pub latency_ms: f32,          // Pre-fill between the input and output streams
+    pub buffer_size: Option<u32>, // Device buffer in frames, None lets the host decide
+    pub adaptive: bool,           // Shrink the pre-fill until underruns start
+}
+
+impl Default for LatencySettings {
+    fn default() -> Self {
+        LatencySettings {
+            latency_ms: 30.0,
+            buffer_size: None,
+            adaptive: false,
+        }
+    }
 }
 
 impl Config for AudioConfig {
@@ -15,6 +40,9 @@ impl Config for AudioConfig {
         AudioConfig {
             previous_input_device: None,
             previous_output_device: None,
+            input_channel: 0,
+            latency: LatencySettings::default(),
+            pipeline: None,
         }
     }
 
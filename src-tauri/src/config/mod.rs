@@ -1,3 +1,4 @@
 pub mod assistant_config;
 pub mod audio_config;
 pub mod config;
+pub mod presets;
@@ -0,0 +1,76 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error};
+
+use crate::audio_backend::pipeline_controller::PipelineState;
+
+/// Named pipelines, one JSON file each in `presets/`.
+pub struct Presets;
+
+impl Presets {
+    fn directory() -> &'static Path {
+        Path::new("presets")
+    }
+
+    pub fn list() -> Result<Vec<String>, Error> {
+        if !Self::directory().exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut names: Vec<String> = fs::read_dir(Self::directory())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "json")
+            })
+            .filter_map(|path| Some(path.file_stem()?.to_string_lossy().into_owned()))
+            .collect();
+        names.sort();
+
+        Ok(names)
+    }
+
+    pub fn load(name: &str) -> Result<PipelineState, Error> {
+        let json = fs::read_to_string(Self::path(name)?)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn save(name: &str, state: &PipelineState) -> Result<(), Error> {
+        let path = Self::path(name)?;
+        fs::create_dir_all(Self::directory())?;
+        fs::write(path, serde_json::to_string_pretty(state)?)?;
+        Ok(())
+    }
+
+    pub fn rename(name: &str, new_name: &str) -> Result<(), Error> {
+        let new_path = Self::path(new_name)?;
+        if new_path.exists() {
+            return Err(anyhow!("A preset called {} already exists", new_name));
+        }
+
+        fs::rename(Self::path(name)?, new_path)?;
+        Ok(())
+    }
+
+    pub fn delete(name: &str) -> Result<(), Error> {
+        fs::remove_file(Self::path(name)?)?;
+        Ok(())
+    }
+
+    /// Preset names become file names, so anything that could leave the directory is rejected.
+    fn path(name: &str) -> Result<PathBuf, Error> {
+        let name = name.trim();
+        if name.is_empty()
+            || name.starts_with('.')
+            || name.contains(|c: char| matches!(c, '/' | '\\' | ':') || c.is_control())
+        {
+            return Err(anyhow!("Invalid preset name: {:?}", name));
+        }
+
+        Ok(Self::directory().join(format!("{}.json", name)))
+    }
+}
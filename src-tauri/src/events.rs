@@ -1,15 +1,28 @@
-use std::{collections::HashMap, sync::MutexGuard};
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    thread,
+    time::Duration,
+};
 
-use tauri::Window;
+use tauri::{AppHandle, Manager, Window};
 
-use crate::audio_backend::{audio_pipeline::AudioPipeline, processor_trait::ProcessorHashMapValue};
+use crate::audio_backend::{
+    pipeline_controller::PipelineController, stream_telemetry::StreamTelemetry,
+};
 
-pub fn emit_pipeline_updated_event(window: Window, pipeline: MutexGuard<AudioPipeline>) {
-    let active_processors: Vec<HashMap<String, ProcessorHashMapValue>> = pipeline
-        .processors
-        .iter()
-        .filter_map(|proc| Some(proc.to_hash_map()))
-        .collect();
+pub fn emit_pipeline_updated_event(window: Window, pipeline: MutexGuard<PipelineController>) {
+    let _ = window.emit("pipeline_updated", pipeline.view());
+}
 
-    let _ = window.emit("pipeline_updated", active_processors);
+/// Emits `stream_stats` once a second so the UI can watch xruns and chain load.
+pub fn start_stream_stats_events(
+    app_handle: AppHandle,
+    telemetry: Arc<StreamTelemetry>,
+    pipeline_controller: Arc<Mutex<PipelineController>>,
+) {
+    thread::spawn(move || loop {
+        let processing_ms = pipeline_controller.lock().unwrap().latency_ms();
+        let _ = app_handle.emit_all("stream_stats", telemetry.stats(processing_ms));
+        thread::sleep(Duration::from_secs(1));
+    });
 }
@@ -5,18 +5,35 @@ use std::sync::{Arc, Mutex};
 
 use amp_sim::{
     assistant::Assistant,
-    audio::start_audio_thread,
-    audio_backend::{audio_device_manager::AudioDeviceManager, audio_pipeline::AudioPipeline},
+    audio::{start_audio_thread, start_pipeline_autosave},
+    audio_backend::{
+        audio_device_manager::AudioDeviceManager, pipeline_controller::PipelineController,
+        processor_registry, stream_telemetry::StreamTelemetry,
+    },
     config::{assistant_config::AssistantConfig, audio_config::AudioConfig, config::Config},
+    events::start_stream_stats_events,
     tauri_commands::{
-        __cmd__add_processor_to_pipeline, __cmd__get_active_processors, __cmd__get_devices,
-        __cmd__get_openai_api_key, __cmd__get_processors, __cmd__init_assistant,
-        __cmd__remove_processor, __cmd__set_input_device, __cmd__set_openai_api_key,
-        __cmd__set_output_device, __cmd__start_audio, __cmd__stop_audio, __cmd__submit_user_prompt,
-        __cmd__update_processor_values, add_processor_to_pipeline, get_active_processors,
-        get_devices, get_openai_api_key, get_processors, init_assistant, remove_processor,
-        set_input_device, set_openai_api_key, set_output_device, start_audio, stop_audio,
-        submit_user_prompt, update_processor_values,
+        __cmd__add_processor_to_pipeline, __cmd__copy_to_other_ab_slot, __cmd__delete_preset,
+        __cmd__get_active_processors, __cmd__get_devices, __cmd__get_history,
+        __cmd__get_input_channels, __cmd__get_latency, __cmd__get_latency_settings,
+        __cmd__get_meters, __cmd__get_openai_api_key, __cmd__get_presets, __cmd__get_processors,
+        __cmd__get_stream_stats, __cmd__init_assistant, __cmd__insert_processor,
+        __cmd__load_preset, __cmd__move_processor, __cmd__pick_impulse_response, __cmd__redo,
+        __cmd__remove_processor, __cmd__rename_preset, __cmd__save_preset,
+        __cmd__set_input_channel, __cmd__set_input_device, __cmd__set_latency_settings,
+        __cmd__set_openai_api_key, __cmd__set_output_device, __cmd__set_processor_bypass,
+        __cmd__set_processor_mix, __cmd__set_processor_parameter, __cmd__set_routing,
+        __cmd__start_audio, __cmd__stop_audio, __cmd__submit_user_prompt, __cmd__swap_processors,
+        __cmd__switch_ab_slot, __cmd__tap_tempo, __cmd__undo, __cmd__update_processor_values,
+        add_processor_to_pipeline, copy_to_other_ab_slot, delete_preset, get_active_processors,
+        get_devices, get_history, get_input_channels, get_latency, get_latency_settings,
+        get_meters, get_openai_api_key, get_presets, get_processors, get_stream_stats,
+        init_assistant, insert_processor, load_preset, move_processor, pick_impulse_response, redo,
+        remove_processor, rename_preset, save_preset, set_input_channel, set_input_device,
+        set_latency_settings, set_openai_api_key, set_output_device, set_processor_bypass,
+        set_processor_mix, set_processor_parameter, set_routing, start_audio, stop_audio,
+        submit_user_prompt, swap_processors, switch_ab_slot, tap_tempo, undo,
+        update_processor_values,
     },
 };
 
@@ -24,11 +41,26 @@ use amp_sim::{
 async fn main() {
     let audio_config = Arc::new(Mutex::new(AudioConfig::retrieve()));
     let audio_device_manager = Arc::new(Mutex::new(AudioDeviceManager::new()));
-    let audio_pipeline = Arc::new(Mutex::new(AudioPipeline::new()));
+    let pipeline_controller = Arc::new(Mutex::new(PipelineController::new()));
+    let stream_telemetry = Arc::new(StreamTelemetry::new());
     let assistant = Arc::new(tokio::sync::Mutex::new(Assistant::new()));
     let assistant_config = Arc::new(tokio::sync::Mutex::new(AssistantConfig::retrieve()));
 
-    let audio_tx = start_audio_thread(audio_device_manager.clone(), audio_pipeline.clone());
+    if let Some(pipeline) = audio_config.lock().unwrap().pipeline.clone() {
+        pipeline_controller
+            .lock()
+            .unwrap()
+            .load_state(&pipeline, processor_registry::restore);
+    }
+    pipeline_controller.lock().unwrap().reset_history();
+    start_pipeline_autosave(pipeline_controller.clone(), audio_config.clone());
+
+    let audio_tx = start_audio_thread(
+        audio_device_manager.clone(),
+        pipeline_controller.clone(),
+        audio_config.clone(),
+        stream_telemetry.clone(),
+    );
 
     if let Some(input_device) = &audio_config.lock().unwrap().previous_input_device {
         let _ = audio_device_manager
@@ -44,24 +76,58 @@ async fn main() {
             .set_output_device(output_device.to_string());
     }
 
+    let stats_telemetry = stream_telemetry.clone();
+    let stats_pipeline_controller = pipeline_controller.clone();
+
     tauri::Builder::default()
+        .setup(move |app| {
+            start_stream_stats_events(app.handle(), stats_telemetry, stats_pipeline_controller);
+            Ok(())
+        })
         .manage(audio_device_manager)
         .manage(audio_tx)
-        .manage(audio_pipeline)
+        .manage(pipeline_controller)
         .manage(audio_config)
+        .manage(stream_telemetry)
         .manage(assistant)
         .manage(assistant_config)
         .invoke_handler(tauri::generate_handler![
             set_input_device,
             set_output_device,
             get_devices,
+            get_input_channels,
+            set_input_channel,
+            get_latency_settings,
+            set_latency_settings,
+            get_latency,
+            get_stream_stats,
             start_audio,
             stop_audio,
             get_processors,
             add_processor_to_pipeline,
+            insert_processor,
+            move_processor,
+            swap_processors,
             update_processor_values,
+            set_processor_parameter,
+            set_processor_bypass,
+            tap_tempo,
+            pick_impulse_response,
+            set_processor_mix,
+            set_routing,
             get_active_processors,
+            get_meters,
             remove_processor,
+            get_presets,
+            save_preset,
+            load_preset,
+            rename_preset,
+            delete_preset,
+            undo,
+            redo,
+            get_history,
+            switch_ab_slot,
+            copy_to_other_ab_slot,
             set_openai_api_key,
             get_openai_api_key,
             init_assistant,
@@ -5,20 +5,28 @@ use std::{
     time::Duration,
 };
 
-use anyhow::{anyhow, Error};
 use cpal::traits::DeviceTrait;
-use tauri::{State, Window};
+use tauri::{api::dialog::blocking::FileDialogBuilder, State, Window};
 
 use crate::{
     assistant::Assistant,
-    audio::{get_processor_impl_names, AudioCommand},
+    audio::AudioCommand,
     audio_backend::{
         audio_device_manager::AudioDeviceManager,
-        audio_pipeline::AudioPipeline,
-        processor_trait::{Processor, ProcessorHashMapValue},
-        processors::{amplifier::Amplifier, screamer::ScreamerPedal},
+        pipeline_controller::{PipelineController, PipelineView, ProcessorId},
+        pipeline_history::HistoryView,
+        processor_registry::{self, ProcessorSchema},
+        processor_trait::ParameterError,
+        routing::{RouteNode, RoutingError},
+        stream_telemetry::{LatencyReport, StreamStats, StreamTelemetry},
+        tap_tempo::TappedParameter,
+    },
+    config::{
+        assistant_config::AssistantConfig,
+        audio_config::{AudioConfig, LatencySettings},
+        config::Config,
+        presets::Presets,
     },
-    config::{assistant_config::AssistantConfig, audio_config::AudioConfig, config::Config},
     events::emit_pipeline_updated_event,
 };
 
@@ -96,6 +104,89 @@ pub fn set_output_device(
     Ok(())
 }
 
+#[tauri::command]
+pub fn get_input_channels(
+    audio_device_manager: State<Arc<Mutex<AudioDeviceManager>>>,
+    audio_config: State<Arc<Mutex<AudioConfig>>>,
+) -> Result<HashMap<String, usize>, String> {
+    let channels = audio_device_manager
+        .lock()
+        .unwrap()
+        .input_device
+        .default_input_config()
+        .map_err(|err| err.to_string())?
+        .channels() as usize;
+
+    Ok(HashMap::from([
+        ("channels".to_string(), channels),
+        (
+            "selected".to_string(),
+            audio_config.lock().unwrap().input_channel,
+        ),
+    ]))
+}
+
+#[tauri::command]
+pub fn set_input_channel(
+    audio_config: State<Arc<Mutex<AudioConfig>>>,
+    tx: State<Sender<AudioCommand>>,
+    channel: usize,
+) -> Result<(), String> {
+    tx.send(AudioCommand::Stop)
+        .expect("Failed to send stop command");
+
+    let mut config_guard = audio_config.lock().unwrap();
+    config_guard.input_channel = channel;
+    let _ = config_guard.save();
+
+    tx.send(AudioCommand::Start)
+        .expect("Failed to send start command");
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_latency_settings(audio_config: State<Arc<Mutex<AudioConfig>>>) -> LatencySettings {
+    audio_config.lock().unwrap().latency.clone()
+}
+
+#[tauri::command]
+pub fn set_latency_settings(
+    audio_config: State<Arc<Mutex<AudioConfig>>>,
+    tx: State<Sender<AudioCommand>>,
+    settings: LatencySettings,
+) -> Result<(), String> {
+    tx.send(AudioCommand::Stop)
+        .expect("Failed to send stop command");
+
+    let mut config_guard = audio_config.lock().unwrap();
+    config_guard.latency = settings;
+    let _ = config_guard.save();
+
+    tx.send(AudioCommand::Start)
+        .expect("Failed to send start command");
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_latency(
+    telemetry: State<Arc<StreamTelemetry>>,
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+) -> LatencyReport {
+    let processing_ms = pipeline_controller.lock().unwrap().latency_ms();
+    telemetry.latency(processing_ms)
+}
+
+#[tauri::command]
+pub fn get_stream_stats(
+    telemetry: State<Arc<StreamTelemetry>>,
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+) -> StreamStats {
+    let processing_ms = pipeline_controller.lock().unwrap().latency_ms();
+    telemetry.stats(processing_ms)
+}
+
 #[tauri::command]
 pub fn get_devices() -> Result<HashMap<String, Vec<String>>, String> {
     let input_devices = AudioDeviceManager::get_input_devices().expect("To get input devices");
@@ -107,91 +198,333 @@ pub fn get_devices() -> Result<HashMap<String, Vec<String>>, String> {
 }
 
 #[tauri::command]
-pub fn get_processors() -> Vec<&'static str> {
-    get_processor_impl_names()
+pub fn get_processors() -> Vec<ProcessorSchema> {
+    processor_registry::schemas()
 }
 
 #[tauri::command]
 pub fn get_active_processors(
-    audio_pipeline: State<Arc<Mutex<AudioPipeline>>>,
-) -> Vec<HashMap<String, ProcessorHashMapValue>> {
-    let active_processors = audio_pipeline.lock().unwrap();
-
-    let active_processors = active_processors
-        .processors
-        .iter()
-        .filter_map(|proc| Some(proc.to_hash_map()))
-        .collect();
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+) -> PipelineView {
+    pipeline_controller.lock().unwrap().view()
+}
 
-    active_processors
+#[tauri::command]
+pub fn get_meters(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+) -> HashMap<ProcessorId, HashMap<String, f32>> {
+    pipeline_controller.lock().unwrap().meters()
 }
 
 #[tauri::command]
 pub fn add_processor_to_pipeline(
-    audio_pipeline: State<Arc<Mutex<AudioPipeline>>>,
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
     window: Window,
     name: String,
-) {
-    let processor = init_processor(&name, None);
+) -> Result<ProcessorId, String> {
+    let proc = processor_registry::create(&name, None).map_err(|err| err.to_string())?;
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let id = pipeline_guard
+        .add_processor(proc)
+        .map_err(|err| format!("Failed to add {}: {}", name, err))?;
+    println!("Added processor: {:#?} ({})", name, id);
+    pipeline_guard.commit(&format!("Add {}", name));
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(id)
+}
+
+
+#[tauri::command]
+pub fn insert_processor(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    name: String,
+    index: usize,
+) -> Result<ProcessorId, String> {
+    let proc = processor_registry::create(&name, None).map_err(|err| err.to_string())?;
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let id = pipeline_guard
+        .insert_processor(index, proc)
+        .map_err(|err| format!("Failed to insert {}: {}", name, err))?;
+    println!("Inserted processor: {:#?} ({}) at {}", name, id, index);
+    pipeline_guard.commit(&format!("Insert {}", name));
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(id)
+}
 
-    if let Ok(proc) = processor {
-        let mut audio_pipeline_guard = audio_pipeline.lock().unwrap();
-        audio_pipeline_guard.add_processor(proc);
-        println!("Added processor: {:#?}", name);
 
-        emit_pipeline_updated_event(window, audio_pipeline_guard)
+#[tauri::command]
+pub fn move_processor(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+    index: usize,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let result = pipeline_guard.move_processor(id, index);
+    if result.is_ok() {
+        pipeline_guard.commit("Move processor");
     }
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    result
 }
 
-fn init_processor(
-    name: &String,
-    values: Option<HashMap<String, String>>,
-) -> Result<Box<dyn Processor>, Error> {
-    match name.to_lowercase().as_str() {
-        "amplifier" => {
-            let mut amplifier = Box::new(Amplifier::new());
-            if let Some(hashmap_values) = values {
-                amplifier.update_values(hashmap_values);
-            }
-            Ok(amplifier)
-        }
-        "screamer" => Ok(Box::new(ScreamerPedal::new())),
-        _ => {
-            println!("Failed to find processor");
-            Err(anyhow!("Processor not found"))
-        }
+#[tauri::command]
+pub fn swap_processors(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    first: ProcessorId,
+    second: ProcessorId,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let result = pipeline_guard.swap_processors(first, second);
+    if result.is_ok() {
+        pipeline_guard.commit("Swap processors");
     }
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    result
 }
 
 #[tauri::command]
 pub fn update_processor_values(
-    pipeline: State<Arc<Mutex<AudioPipeline>>>,
-    processor_name: String,
-    values: HashMap<String, String>,
-) -> HashMap<String, String> {
-    let mut pipeline_guard = pipeline.lock().unwrap();
-    let processors = &mut pipeline_guard.processors;
-    if let Some(proc) = processors
-        .iter_mut()
-        .find(|proc| proc.get_name() == processor_name)
-    {
-        proc.update_values(values.clone());
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+    values: HashMap<String, f32>,
+) -> Result<HashMap<String, f32>, ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.update_processor_values(id, &values)?;
+    let label = format!("Update processor {}", id);
+    match values.keys().next() {
+        Some(name) if values.len() == 1 => pipeline_guard.commit_edit(&label, id, name),
+        _ => pipeline_guard.commit(&label),
     }
+    println!("Updated {}: {:?}", id, values);
 
-    values
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(values)
+}
+
+#[tauri::command]
+pub fn set_processor_parameter(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+    name: String,
+    value: f32,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.set_processor_parameter(id, &name, value)?;
+    pipeline_guard.commit_edit(&format!("Set {} on processor {}", name, id), id, &name);
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_routing(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    routing: Vec<RouteNode>,
+) -> Result<(), RoutingError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let result = pipeline_guard.set_routing(routing);
+    if result.is_ok() {
+        pipeline_guard.commit("Change routing");
+    }
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    result
+}
+
+#[tauri::command]
+pub fn tap_tempo(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+) -> Result<Option<TappedParameter>, ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let tapped = pipeline_guard.tap_tempo(id)?;
+    if let Some(tapped) = tapped {
+        pipeline_guard.commit_edit(&format!("Tap tempo {}", id), id, tapped.name);
+        emit_pipeline_updated_event(window, pipeline_guard);
+    }
+
+    Ok(tapped)
+}
+
+/// Asks for a WAV file and loads it as a cabinet's impulse response. Returns the path, or
+/// `None` if the dialog was cancelled.
+#[tauri::command]
+pub async fn pick_impulse_response(
+    pipeline_controller: State<'_, Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+) -> Result<Option<String>, String> {
+    let Some(path) = FileDialogBuilder::new()
+        .add_filter("WAV", &["wav"])
+        .pick_file()
+    else {
+        return Ok(None);
+    };
+    let path = path.to_string_lossy().to_string();
+
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+    pipeline_guard
+        .load_processor_file(id, "impulse_response", &path)
+        .map_err(|err| err.to_string())?;
+    pipeline_guard.commit("Load impulse response");
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(Some(path))
+}
+
+#[tauri::command]
+pub fn set_processor_bypass(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+    bypassed: bool,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.set_processor_bypass(id, bypassed)?;
+    pipeline_guard.commit(if bypassed { "Bypass" } else { "Enable" });
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn set_processor_mix(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+    mix: f32,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.set_processor_mix(id, mix)?;
+    pipeline_guard.commit_edit(&format!("Set mix on processor {}", id), id, "mix");
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(())
 }
 
 #[tauri::command]
 pub fn remove_processor(
-    pipeline: State<Arc<Mutex<AudioPipeline>>>,
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    id: ProcessorId,
+) -> Result<(), ParameterError> {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let result = pipeline_guard.remove_processor(id);
+    if result.is_ok() {
+        pipeline_guard.commit("Remove processor");
+    }
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    result
+}
+
+#[tauri::command]
+pub fn undo(pipeline_controller: State<Arc<Mutex<PipelineController>>>, window: Window) -> bool {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let undone = pipeline_guard.undo(processor_registry::restore);
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    undone
+}
+
+#[tauri::command]
+pub fn redo(pipeline_controller: State<Arc<Mutex<PipelineController>>>, window: Window) -> bool {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    let redone = pipeline_guard.redo(processor_registry::restore);
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+    redone
+}
+
+#[tauri::command]
+pub fn get_history(pipeline_controller: State<Arc<Mutex<PipelineController>>>) -> HistoryView {
+    pipeline_controller.lock().unwrap().history()
+}
+
+#[tauri::command]
+pub fn switch_ab_slot(pipeline_controller: State<Arc<Mutex<PipelineController>>>, window: Window) {
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.switch_ab_slot(processor_registry::restore);
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+}
+
+#[tauri::command]
+pub fn copy_to_other_ab_slot(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
     window: Window,
-    processor_name: String,
 ) {
-    let mut pipeline_guard = pipeline.lock().unwrap();
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.copy_to_other_ab_slot();
+
+    emit_pipeline_updated_event(window, pipeline_guard);
+}
 
-    pipeline_guard.remove_processor(processor_name);
+#[tauri::command]
+pub fn get_presets() -> Result<Vec<String>, String> {
+    Presets::list().map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn save_preset(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    name: String,
+) -> Result<(), String> {
+    let state = pipeline_controller.lock().unwrap().state();
+
+    Presets::save(&name, &state).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn load_preset(
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
+    window: Window,
+    name: String,
+) -> Result<(), String> {
+    let state = Presets::load(&name).map_err(|err| err.to_string())?;
+    let mut pipeline_guard = pipeline_controller.lock().unwrap();
+
+    pipeline_guard.load_state(&state, processor_registry::restore);
+    pipeline_guard.commit(&format!("Load preset {}", name));
 
     emit_pipeline_updated_event(window, pipeline_guard);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn rename_preset(name: String, new_name: String) -> Result<(), String> {
+    Presets::rename(&name, &new_name).map_err(|err| err.to_string())
+}
+
+#[tauri::command]
+pub fn delete_preset(name: String) -> Result<(), String> {
+    Presets::delete(&name).map_err(|err| err.to_string())
 }
 
 #[tauri::command]
@@ -256,12 +589,12 @@ pub fn init_assistant(
 #[tauri::command]
 pub fn submit_user_prompt(
     assistant: State<Arc<tokio::sync::Mutex<Assistant>>>,
-    audio_pipeline: State<Arc<Mutex<AudioPipeline>>>,
+    pipeline_controller: State<Arc<Mutex<PipelineController>>>,
     window: Window,
     prompt: String,
 ) -> Result<(), String> {
     let assistant_clone = Arc::clone(&assistant);
-    let audio_pipeline_clone = Arc::clone(&audio_pipeline);
+    let pipeline_controller_clone = Arc::clone(&pipeline_controller);
 
     tokio::spawn(async move {
         let assistant_guard = assistant_clone.lock().await;
@@ -285,26 +618,52 @@ pub fn submit_user_prompt(
             .get_parsed_assistant_response(response)
             .unwrap();
 
+        let mut pipeline_guard = pipeline_controller_clone.lock().unwrap();
+        pipeline_guard.clear();
+
         for processor_map in &assistant_response.processors {
             for (processor_name, settings) in processor_map {
-                let mut new_settings_map: HashMap<String, String> = HashMap::new();
+                let Some(schema) =
+                    processor_registry::find(processor_name).map(|kind| kind.schema())
+                else {
+                    eprintln!("Ignoring unknown processor {}", processor_name);
+                    continue;
+                };
+
+                // Start from the defaults so settings the assistant left out are still valid.
+                let mut new_settings_map: HashMap<String, f32> = schema
+                    .parameters
+                    .iter()
+                    .map(|param| (param.name.to_string(), param.default))
+                    .collect();
 
                 for (setting_name, setting_value) in settings {
-                    new_settings_map.insert(setting_name.to_owned(), setting_value.to_string());
+                    let Some(param) = schema
+                        .parameters
+                        .iter()
+                        .find(|param| param.name == setting_name.as_str())
+                    else {
+                        eprintln!(
+                            "Ignoring unknown setting {} for {}",
+                            setting_name, schema.name
+                        );
+                        continue;
+                    };
+
+                    new_settings_map.insert(setting_name.to_owned(), param.clamp(*setting_value));
                 }
 
-                let processor = init_processor(processor_name, Some(new_settings_map));
+                let processor = processor_registry::create(processor_name, Some(&new_settings_map));
                 if let Ok(proc) = processor {
-                    audio_pipeline_clone.lock().unwrap().add_processor(proc);
-                    println!("Added processor: {:#?}", processor_name)
+                    if pipeline_guard.add_processor(proc).is_ok() {
+                        println!("Added processor: {:#?}", processor_name)
+                    }
                 }
             }
         }
+        pipeline_guard.commit("Assistant");
 
-        let mut audio_pipeline_guard = audio_pipeline_clone.lock().unwrap();
-        audio_pipeline_guard.processors.clear();
-
-        emit_pipeline_updated_event(window, audio_pipeline_guard);
+        emit_pipeline_updated_event(window, pipeline_guard);
     });
 
     Ok(())
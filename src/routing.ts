@@ -0,0 +1,73 @@
+// Helpers for editing the routing graph in place before it is sent back with `set_routing`.
+
+export const MAX_SPLIT_DEPTH = 4;
+
+export type RouteList = {
+  label: string;
+  nodes: Array<RouteNode>;
+};
+
+// Every list a processor can be moved into, starting with the main chain.
+export function routeLists(
+  nodes: Array<RouteNode>,
+  label = "Main chain",
+): Array<RouteList> {
+  let lists = [{ label, nodes }];
+
+  nodes.forEach((node, index) => {
+    if (node.type === "split") {
+      node.branches.forEach((branch, branchIndex) => {
+        lists = lists.concat(
+          routeLists(
+            branch.nodes,
+            `${label} / Split ${index + 1} / Branch ${branchIndex + 1}`,
+          ),
+        );
+      });
+    }
+  });
+
+  return lists;
+}
+
+// Removes a processor from wherever it is routed and returns its node.
+export function takeProcessor(
+  nodes: Array<RouteNode>,
+  id: number,
+): RouteNode | undefined {
+  let index = nodes.findIndex(
+    (node) => node.type === "processor" && node.id === id,
+  );
+  if (index !== -1) {
+    return nodes.splice(index, 1)[0];
+  }
+
+  for (let node of nodes) {
+    if (node.type === "split") {
+      for (let branch of node.branches) {
+        let taken = takeProcessor(branch.nodes, id);
+        if (taken) {
+          return taken;
+        }
+      }
+    }
+  }
+}
+
+export function newSplit(): RouteNode {
+  return {
+    type: "split",
+    branches: [
+      { gain: 1, nodes: [] },
+      { gain: 1, nodes: [] },
+    ],
+  };
+}
+
+// Replaces a split with the contents of its branches, in order.
+export function unsplit(nodes: Array<RouteNode>, index: number) {
+  let split = nodes[index];
+  if (split.type === "split") {
+    nodes.splice(index, 1, ...split.branches.flatMap((branch) => branch.nodes));
+  }
+}
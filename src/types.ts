@@ -2,3 +2,113 @@ type InitialDevices = {
   input: string;
   output: string;
 };
+
+type InputChannels = {
+  channels: number;
+  selected: number;
+};
+
+type LatencySettings = {
+  latency_ms: number;
+  buffer_size: number | null;
+  adaptive: boolean;
+};
+
+type LatencyReport = {
+  input_ms: number;
+  buffer_ms: number;
+  target_buffer_ms: number;
+  output_ms: number;
+  processing_ms: number;
+  round_trip_ms: number;
+};
+
+type StreamStats = {
+  overruns: number;
+  underruns: number;
+  callback_us: number;
+  max_callback_us: number;
+  cpu_load: number;
+  peak_cpu_load: number;
+  latency: LatencyReport;
+};
+
+type ParameterDescriptor = {
+  name: string;
+  label: string;
+  min: number;
+  max: number;
+  default: number;
+  unit: string;
+  scale: "linear" | "log" | "decibel";
+  kind:
+    | { type: "continuous" }
+    | { type: "stepped" }
+    | { type: "toggle" }
+    | { type: "choice"; options: Array<string> };
+};
+
+type ProcessorSchema = {
+  name: string;
+  display_name: string;
+  category: string;
+  parameters: Array<ParameterDescriptor>;
+};
+
+type ParameterError =
+  | { kind: "unknown_processor"; name: string }
+  | { kind: "unknown_parameter"; processor: string; name: string }
+  | {
+      kind: "out_of_range";
+      name: string;
+      value: number;
+      min: number;
+      max: number;
+    }
+  | { kind: "not_a_whole_number"; name: string; value: number };
+
+type Branch = {
+  gain: number;
+  nodes: Array<RouteNode>;
+};
+
+type RouteNode =
+  | { type: "processor"; id: number }
+  | { type: "split"; branches: Array<Branch> };
+
+type RoutingError = {
+  kind: string;
+  id?: number;
+  max?: number;
+  gain?: number;
+};
+
+type HistoryView = {
+  undo: Array<string>;
+  redo: Array<string>;
+};
+
+type ProcessorState = {
+  id: number;
+  name: string;
+  values: Record<string, number>;
+  slot: { bypassed: boolean; mix: number };
+};
+
+type PipelineState = {
+  processors: Array<ProcessorState>;
+  routing: Array<RouteNode>;
+};
+
+type AbView = {
+  active: "a" | "b";
+  a: PipelineState;
+  b: PipelineState;
+};
+
+type PipelineView = {
+  processors: Array<any>;
+  routing: Array<RouteNode>;
+  history: HistoryView;
+  ab: AbView;
+};